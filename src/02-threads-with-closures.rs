@@ -1,15 +1,13 @@
-use std::thread;
+use threads_in_rust::thread_pool::ThreadPool;
 
 fn main() {
     let numbers = Vec::from_iter(0..=1000);
+    let pool = ThreadPool::new(4);
 
-    let t = thread::spawn(move || {
-        let len = numbers.len();
-        let sum = numbers.iter().sum::<usize>();
+    // the closures only borrow the numbers, the pool splits them up between
+    // its workers and adds up the partial sums
+    let sum = pool.par_reduce(numbers.as_slice(), || 0, |n| *n, |a, b| a + b);
 
-        sum / len
-    });
-
-    let average = t.join().unwrap();
+    let average = sum / numbers.len();
     println!("average: {average}");
 }
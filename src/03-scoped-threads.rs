@@ -1,5 +1,7 @@
 use std::thread;
 
+use threads_in_rust::barrier::Barrier;
+
 fn main() {
     let numbers = vec![1, 2, 3];
 
@@ -13,4 +15,21 @@ fn main() {
             }
         });
     });
+
+    // every thread finishes a phase before any of them starts the next one
+    let barrier = Barrier::new(numbers.len() as u32);
+
+    thread::scope(|s| {
+        for n in &numbers {
+            let barrier = &barrier;
+            s.spawn(move || {
+                for phase in 1..=2 {
+                    println!("{n} in phase {phase}");
+                    if barrier.wait().is_leader() {
+                        println!("{n} was the last one to finish phase {phase}");
+                    }
+                }
+            });
+        }
+    });
 }
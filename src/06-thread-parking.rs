@@ -3,23 +3,32 @@ use std::sync::Mutex;
 use std::thread;
 use std::time::Duration;
 
+use threads_in_rust::parker::Parker;
+
 fn main() {
     let queue = Mutex::new(VecDeque::new());
 
+    // unlike thread::park, only our unparker wakes up the consumer
+    let parker = Parker::new();
+    let unparker = parker.unparker();
+
     thread::scope(|s| {
-        let t = s.spawn(|| loop {
+        let queue = &queue;
+
+        // the parker moves into the thread, it can't be shared
+        s.spawn(move || loop {
             let item = queue.lock().unwrap().pop_front();
 
             if let Some(item) = item {
                 dbg!(item);
             } else {
-                thread::park();
+                parker.park();
             }
         });
 
         for i in 0.. {
             queue.lock().unwrap().push_back(i);
-            t.thread().unpark();
+            unparker.unpark();
             thread::sleep(Duration::from_secs(1));
         }
     });
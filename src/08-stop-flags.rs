@@ -1,32 +1,135 @@
-use std::sync::atomic::AtomicBool;
+use std::io::{stdin, stdout};
 use std::sync::atomic::Ordering::Relaxed;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64};
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
+use threads_in_rust::cancellation::CancellationToken;
+use threads_in_rust::command::Commands;
+#[cfg(unix)]
+use threads_in_rust::signal;
+
+// set by the commands, the background thread only ever reads them
+static PAUSED: AtomicBool = AtomicBool::new(false);
+static RATE: AtomicU32 = AtomicU32::new(1);
+static DONE: AtomicU64 = AtomicU64::new(0);
+
 fn main() {
-    static STOP: AtomicBool = AtomicBool::new(false);
+    let stop = CancellationToken::new();
 
-    let background_thread = thread::spawn(|| {
-        while !STOP.load(Relaxed) {
-            some_work();
+    let background_thread = thread::spawn({
+        let stop = stop.clone();
+        move || {
+            while !stop.is_cancelled() {
+                if PAUSED.load(Relaxed) {
+                    // resume and stop unpark the thread
+                    thread::park();
+                    continue;
+                }
+                some_work(&stop);
+            }
         }
     });
 
-    // Use the main thread to listen for user input
-    for line in std::io::stdin().lines() {
-        match line.unwrap().as_str() {
-            "help" => println!("commands: help, stop"),
-            "stop" => break,
-            cmd => println!("unknown command: {cmd:?}"),
+    let worker = background_thread.thread().clone();
+    stop.on_cancel(move || {
+        println!("stopping");
+        worker.unpark();
+    });
+
+    // Ctrl-C and kill stop the background thread too
+    #[cfg(unix)]
+    let _signal_watcher = match signal::cancel_on_signal(&stop) {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            eprintln!("signals won't stop the work: {e}");
+            None
         }
+    };
+
+    let commands = Arc::new(commands(&stop, background_thread.thread().clone()));
+
+    // e.g. `cargo run --bin stop-flags /tmp/stop-flags.sock`, then talk to it
+    // with `nc -U /tmp/stop-flags.sock`
+    #[cfg(unix)]
+    if let Some(path) = std::env::args().nth(1) {
+        Arc::clone(&commands)
+            .serve_unix_socket(path, &stop)
+            .unwrap();
     }
 
-    STOP.store(true, Relaxed);
+    // Listen for user input on a thread of its own, which is still blocked
+    // reading stdin when a signal arrives, so it isn't joined
+    thread::spawn({
+        let stop = stop.clone();
+        move || {
+            commands.serve(stdin().lock(), stdout(), &stop).unwrap();
+            stop.cancel();
+        }
+    });
+
+    while !stop.wait_cancelled(Duration::from_secs(60)) {}
 
     background_thread.join().unwrap();
+
+    #[cfg(unix)]
+    if let Some(signal) = signal::received() {
+        println!("stopped by signal {signal}");
+    }
+}
+
+fn commands(stop: &CancellationToken, worker: thread::Thread) -> Commands {
+    let mut commands = Commands::new();
+
+    commands
+        .register("pause", "pauses the work", |_| {
+            PAUSED.store(true, Relaxed);
+            Ok("paused".into())
+        })
+        .register("resume", "resumes the work", move |_| {
+            PAUSED.store(false, Relaxed);
+            worker.unpark();
+            Ok("resumed".into())
+        })
+        .register("status", "shows what the work is up to", |_| {
+            let state = if PAUSED.load(Relaxed) {
+                "paused"
+            } else {
+                "running"
+            };
+            Ok(format!(
+                "{state}, {} done at {}/s",
+                DONE.load(Relaxed),
+                RATE.load(Relaxed)
+            ))
+        })
+        .register("set-rate N", "does the work N times per second", |args| {
+            let [rate] = args else {
+                return Err("usage: set-rate N".into());
+            };
+            match rate.parse() {
+                Ok(rate @ 1..) => {
+                    RATE.store(rate, Relaxed);
+                    Ok(format!("working {rate} times per second"))
+                }
+                _ => Err(format!("not a positive number: {rate}")),
+            }
+        })
+        .register("stop", "stops the work and exits", {
+            let stop = stop.clone();
+            move |_| {
+                stop.cancel();
+                Ok(String::new())
+            }
+        });
+
+    commands
 }
 
-fn some_work() {
+fn some_work(stop: &CancellationToken) {
     println!("doing some work");
-    thread::sleep(Duration::from_secs(2));
+    DONE.fetch_add(1, Relaxed);
+    // unlike sleeping, this returns right away once stopped
+    stop.wait_cancelled(Duration::from_secs(1) / RATE.load(Relaxed));
 }
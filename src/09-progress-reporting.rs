@@ -1,38 +1,35 @@
-use std::io::{stdout, Write};
-use std::sync::atomic::AtomicUsize;
-use std::sync::atomic::Ordering::Relaxed;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+use threads_in_rust::progress::MultiProgress;
 
 fn main() {
-    let num_done = AtomicUsize::new(0);
-    let main_thread = thread::current();
+    // four background threads processing 25 items each
+    let progress = MultiProgress::new((0..4).map(|t| (format!("worker-{t}"), 25)));
 
     // takes care of joining all the threads
-    thread::scope(|s| {
-        // a background thread to process all 100 items
-
-        s.spawn(|| {
-            for i in 0..100 {
-                process_item(i);
-                num_done.store(i + 1, Relaxed);
-                main_thread.unpark();
-            }
-        });
-
-        loop {
-            let n = num_done.load(Relaxed);
-            print!("\rWorking ... {n}/100 done");
-            stdout().flush().unwrap();
-            if n == 100 {
-                break;
-            }
-
-            thread::park_timeout(Duration::from_secs(1));
+    let reported = thread::scope(|s| {
+        for t in 0..4 {
+            let progress = &progress;
+            s.spawn(move || {
+                let mut recorder = progress.bar(t).recorder();
+                for i in 0..25 {
+                    let start = Instant::now();
+                    process_item(t * 25 + i);
+                    // never waits for the terminal, the main thread draws
+                    recorder.record(start.elapsed());
+                }
+            });
         }
+
+        // redraws all bars whenever an item is done
+        progress.report(Duration::from_secs(1))
     });
 
-    println!("\nDone");
+    match reported {
+        Ok(()) => println!("Done"),
+        Err(e) => eprintln!("Stopped reporting progress: {e}"),
+    }
 }
 
 fn process_item(_i: usize) {
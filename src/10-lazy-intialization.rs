@@ -1,42 +1,72 @@
-use std::io::{stdout, Write};
-use std::sync::atomic::AtomicUsize;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::atomic::AtomicU64;
 use std::sync::atomic::Ordering::Relaxed;
 use std::thread;
 use std::time::Duration;
 
+use threads_in_rust::once::{Lazy, Once, OnceLock, OnceNonZeroUsize};
+
+// Built the first time it's used, by whichever thread uses it first
+static SETTINGS: Lazy<HashMap<&str, u64>> = Lazy::new(|| {
+    println!("loading the settings");
+    thread::sleep(Duration::from_millis(100));
+    HashMap::from([("workers", 4), ("items", 100)])
+});
+
+static KEY: OnceLock<u64> = OnceLock::new();
+static BANNER: Once = Once::new();
+
 fn main() {
-    let num_done = AtomicUsize::new(0);
-    let main_thread = thread::current();
+    // every thread might compute x, only the result is the same
+    thread::scope(|s| {
+        for t in 0..4 {
+            s.spawn(move || {
+                println!("thread {t}: x = {}, {} cpus", get_x(), num_cpus());
+            });
+        }
+    });
 
-    // takes care of joining all the threads
+    // only one thread computes the key, the others wait for it
     thread::scope(|s| {
-        // a background thread to process all 100 items
-
-        s.spawn(|| {
-            for i in 0..100 {
-                process_item(i);
-                num_done.store(i + 1, Relaxed);
-                main_thread.unpark();
-            }
-        });
-
-        loop {
-            let n = num_done.load(Relaxed);
-            print!("\rWorking ... {n}/100 done");
-            stdout().flush().unwrap();
-            if n == 100 {
-                break;
-            }
-
-            thread::park_timeout(Duration::from_secs(1));
+        for t in 0..4 {
+            s.spawn(move || {
+                BANNER.call_once(|| println!("thread {t} is first"));
+                let key = KEY.get_or_init(|| generate_key(t));
+                let workers = SETTINGS["workers"];
+                println!("thread {t}: key = {key}, {workers} workers");
+            });
         }
     });
+}
+
+// The racy version from the book: 0 means not initialized yet
+fn get_x() -> u64 {
+    static X: AtomicU64 = AtomicU64::new(0);
+
+    let mut x = X.load(Relaxed);
+    if x == 0 {
+        x = calculate_x();
+        X.store(x, Relaxed);
+    }
+    x
+}
+
+// Still racy, but every thread ends up with the value that was stored first
+fn num_cpus() -> NonZeroUsize {
+    static NUM_CPUS: OnceNonZeroUsize = OnceNonZeroUsize::new();
 
-    println!("\nDone");
+    NUM_CPUS.get_or_init(|| thread::available_parallelism().unwrap_or(NonZeroUsize::MIN))
 }
 
-fn process_item(_i: usize) {
-    let wait = rand::random::<u8>();
+fn calculate_x() -> u64 {
+    println!("calculating x");
+    thread::sleep(Duration::from_millis(100));
+    123
+}
 
-    thread::sleep(Duration::from_millis(wait as u64));
+fn generate_key(thread: usize) -> u64 {
+    println!("thread {thread} generates the key");
+    thread::sleep(Duration::from_millis(100));
+    rand::random()
 }
@@ -1,50 +1,43 @@
-use std::sync::atomic::Ordering::Relaxed;
-use std::sync::atomic::{AtomicU64, AtomicUsize};
+use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
 
-fn main() {
-    let num_done = &AtomicUsize::new(0);
-    let total_time = &AtomicU64::new(0);
-    let max_time = &AtomicU64::new(0);
-
-    thread::scope(|s| {
-        for t in 0..4 {
-            s.spawn(move || {
-                for i in 0..25 {
-                    let start = Instant::now();
-                    process_item(t * 25 + i);
-                    let time_taken = start.elapsed().as_micros() as u64;
-
-                    num_done.fetch_add(1, Relaxed);
-                    total_time.fetch_add(time_taken, Relaxed);
-                    max_time.fetch_max(time_taken, Relaxed);
-                }
-            });
-        }
-
-        loop {
-            let n = num_done.load(Relaxed);
-            let total_time = Duration::from_micros(total_time.load(Relaxed));
-            let max_time = Duration::from_micros(max_time.load(Relaxed));
-
-            if n == 0 {
-                println!("Working ... nothing done yet.");
-            } else {
-                println!(
-                    "Working ... {n}/100, {:?} average, {:?} peak",
-                    total_time / n as u32,
-                    max_time
-                );
-            }
+use threads_in_rust::progress::ProgressTracker;
+use threads_in_rust::wait_group::WaitGroup;
 
-            thread::sleep(Duration::from_secs(1));
-            if n == 100 {
-                break;
+fn main() {
+    let tracker = Arc::new(ProgressTracker::new(100));
+    let workers = WaitGroup::new();
+
+    // not scoped, so the workers are waited for with the WaitGroup instead
+    for t in 0..4 {
+        let tracker = Arc::clone(&tracker);
+        let worker = workers.clone();
+        thread::spawn(move || {
+            let mut recorder = tracker.recorder();
+            for i in 0..25 {
+                let start = Instant::now();
+                process_item(t * 25 + i);
+                recorder.record(start.elapsed());
             }
+            drop(worker);
+        });
+    }
+
+    let reporter = tracker.spawn_reporter(Duration::from_secs(1), |progress| {
+        match (progress.average, progress.p50, progress.p99, progress.eta) {
+            (Some(average), Some(p50), Some(p99), Some(eta)) => println!(
+                "Working ... {}/{}, {average:?} average, {p50:?} p50, {p99:?} p99, {:?} peak, {:.1} items/s, {eta:.1?} left",
+                progress.done, progress.total, progress.peak, progress.rate
+            ),
+            _ => println!("Working ... nothing done yet."),
         }
     });
 
+    // blocks until every worker dropped its clone
+    workers.wait();
+    reporter.join().unwrap();
+
     println!("\nDone");
 }
 
@@ -1,89 +1,97 @@
-use std::sync::mpsc::{channel, Receiver, Sender};
-use std::sync::{Arc, Mutex};
 use std::thread;
-use std::thread::JoinHandle;
+use std::time::Duration;
 
-type Job = Box<dyn FnOnce() + Send + 'static>;
+use threads_in_rust::semaphore::Semaphore;
+use threads_in_rust::thread_pool::{PoolEvent, Priority, RejectionPolicy, ThreadPool};
 
-pub enum Message {
-    NewJob(Job),
-    Terminate,
-}
-
-pub struct ThreadPool {
-    workers: Vec<Worker>,
-    sender: Sender<Message>,
-}
-
-impl ThreadPool {
-    pub fn new(size: usize) -> Self {
-        assert!(size > 0);
+fn main() {
+    // starts with 2 workers and spawns up to 10 while jobs pile up, and
+    // execute() blocks while there are 1000 jobs waiting already
+    let pool = ThreadPool::builder()
+        .thread_name_prefix("worker-")
+        .min_threads(2)
+        .max_threads(10)
+        .queue_capacity(1000)
+        .rejection_policy(RejectionPolicy::Block)
+        .on_event(|event| match event {
+            PoolEvent::JobStarted { worker, .. } => {
+                println!("Worker {worker} got a job; executing.")
+            }
+            PoolEvent::WorkerRetired { worker } => {
+                println!("Worker {worker} was idle for too long; retiring.")
+            }
+            PoolEvent::WorkerTerminated { worker } => {
+                println!("Worker {worker} was told to terminate.")
+            }
+            _ => {}
+        })
+        .build()
+        .unwrap();
 
-        let (sender, receiver) = channel::<Message>();
-        let receiver = Arc::new(Mutex::new(receiver));
+    for i in 0..100000 {
+        pool.execute(move || println!("Hello from thread {:?}, job {i}", thread::current().name()));
+    }
 
-        let mut workers = Vec::with_capacity(size);
-        for i in 0..size {
-            workers.push(Worker::new(i, Arc::clone(&receiver)));
-        }
+    // jumps ahead of the jobs that are still waiting in the queue
+    pool.execute_with_priority(Priority::Critical, || println!("Urgent job"));
 
-        Self { workers, sender }
+    // only waits for the jobs of the group, not for the ones queued above
+    let group = pool.job_group();
+    for i in 0..10 {
+        group.execute(move || println!("Grouped job {i}"));
     }
-
-    pub fn execute<F>(&self, f: F)
-    where
-        F: FnOnce() + Send + 'static,
-    {
-        let job = Box::new(f);
-        self.sender.send(Message::NewJob(job)).unwrap()
+    if !group.wait_timeout(Duration::from_secs(5)) {
+        println!("{} grouped jobs are still pending", group.pending());
     }
-}
-
-impl Drop for ThreadPool {
-    fn drop(&mut self) {
-        for _ in &self.workers {
-            self.sender.send(Message::Terminate).unwrap();
-        }
-
-        for worker in &mut self.workers {
-            if let Some(thread) = worker.thread.take() {
-                thread.join().unwrap();
-            }
-        }
+    pool.wait_idle();
+
+    // however many workers there are, only 3 jobs are connected at a time
+    static CONNECTIONS: Semaphore = Semaphore::new(3);
+    for i in 0..10 {
+        pool.execute(move || {
+            let _connection = CONNECTIONS.acquire();
+            println!("Job {i} is connected");
+            thread::sleep(Duration::from_millis(100));
+        });
     }
-}
+    pool.wait_idle();
 
-struct Worker {
-    thread: Option<JoinHandle<()>>,
-}
+    let numbers = vec![1, 2, 3];
 
-impl Worker {
-    fn new(id: usize, receiver: Arc<Mutex<Receiver<Message>>>) -> Self {
-        let thread = thread::spawn(move || loop {
-            let message = receiver.lock().unwrap().recv().unwrap();
-
-            match message {
-                Message::NewJob(job) => {
-                    println!("Worker {} got a job; executing.", id);
-                    job();
-                }
-                Message::Terminate => {
-                    println!("Worker {} was told to terminate.", id);
-                    break;
-                }
+    // just like thread::scope, but the jobs run on the pool's threads
+    pool.scope(|s| {
+        s.spawn(|| {
+            println!("length: {}", numbers.len());
+        });
+        s.spawn(|| {
+            for n in &numbers {
+                println!("{n}");
             }
         });
-
-        Self {
-            thread: Some(thread),
-        }
-    }
-}
-
-fn main() {
-    let pool = ThreadPool::new(10);
-
-    for i in 0..100000 {
-        pool.execute(move || println!("Hello from thread {:?}, job {i}", thread::current().id()));
+    });
+
+    // the pool decides how to split the work, depending on how busy it is
+    let numbers = Vec::from_iter(0..=1000);
+    let sum = pool.par_reduce(numbers.as_slice(), || 0, |n| *n, |a, b| a + b);
+    println!("average: {}", sum / numbers.len());
+
+    // recurring work without a thread of its own that sleeps in between
+    let heartbeat = pool.schedule_at_fixed_rate(Duration::from_millis(500), || {
+        println!("Still alive");
+    });
+    pool.schedule_after(Duration::from_secs(2), move || heartbeat.cancel());
+    thread::sleep(Duration::from_secs(3));
+
+    let stats = pool.stats();
+    println!(
+        "{} jobs done on {} workers, average time {:?}",
+        stats.completed_jobs,
+        stats.num_workers,
+        stats.average_execution_time()
+    );
+
+    // instead of waiting forever when dropping the pool, give up after a while
+    if let Err(e) = pool.shutdown_timeout(Duration::from_secs(10)) {
+        println!("{e}");
     }
 }
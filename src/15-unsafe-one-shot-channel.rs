@@ -3,7 +3,9 @@ use std::marker::PhantomData;
 use std::mem::MaybeUninit;
 use std::sync::atomic::AtomicU8;
 use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
-use std::thread::{self, Thread};
+use std::thread;
+
+use threads_in_rust::parker::{Parker, Unparker};
 
 const EMPTY: u8 = 0;
 const WRITING: u8 = 1;
@@ -12,7 +14,7 @@ const READING: u8 = 2;
 
 pub struct Sender<'a, T> {
     channel: &'a Channel<T>,
-    receiving_thread: Thread,
+    unparker: Unparker,
 }
 
 impl<T> Sender<'_, T> {
@@ -28,27 +30,28 @@ impl<T> Sender<'_, T> {
 
         unsafe { (*self.channel.message.get()).write(message) };
         self.channel.state.store(READY, Release);
-        self.receiving_thread.unpark();
+        self.unparker.unpark();
     }
 }
 
 pub struct Receiver<'a, T> {
     channel: &'a Channel<T>,
+    parker: Parker,
     _no_send: PhantomData<*const ()>, // disallow sending the Receiver between threads, `*const ()`
                                       // is a raw pointer, that does not implement Send
 }
 
 impl<T> Receiver<'_, T> {
     pub fn receive(self) -> T {
-        // thread::park() might return spuriously, so the loop needs to be there to check if we
-        // still need to block
+        // unlike thread::park(), the parker only returns once the sender unparked it, but the
+        // loop is still needed in case it was unparked before the message was READY
         while self
             .channel
             .state
             .compare_exchange(READY, READING, Acquire, Relaxed)
             .is_err()
         {
-            thread::park();
+            self.parker.park();
         }
 
         unsafe { (*self.channel.message.get()).assume_init_read() }
@@ -74,13 +77,15 @@ impl<T> Channel<T> {
 
     pub fn split(&mut self) -> (Sender<T>, Receiver<T>) {
         *self = Self::new();
+        let parker = Parker::new();
         (
             Sender {
                 channel: self,
-                receiving_thread: thread::current(),
+                unparker: parker.unparker(),
             },
             Receiver {
                 channel: self,
+                parker,
                 _no_send: PhantomData,
             },
         )
@@ -1,64 +1,20 @@
-use std::cell::UnsafeCell;
-use std::ops::{Deref, DerefMut};
-use std::sync::atomic::AtomicU32;
-use std::sync::atomic::Ordering::{Acquire, Release};
+use std::thread;
 
-use atomic_wait::{wait, wake_one};
+use threads_in_rust::mutex::Mutex;
 
-pub struct Mutex<T> {
-    /// 0: unlocked
-    /// 1: locked
-    state: AtomicU32,
-    value: UnsafeCell<T>,
-}
-
-impl<T> Mutex<T> {
-    pub const fn new(value: T) -> Self {
-        Self {
-            state: AtomicU32::new(0), // initial unlocked state
-            value: UnsafeCell::new(value),
-        }
-    }
+fn main() {
+    let counter = Mutex::new(0);
 
-    pub fn lock(&self) -> MutexGuard<T> {
-        // Set the state to 1: locked
-        while self.state.swap(1, Acquire) == 1 {
-            // If it was already locked
-            // wait unless the state is no longer 1
-            wait(&self.state, 1);
+    thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|| {
+                for _ in 0..100_000 {
+                    *counter.lock() += 1;
+                }
+            });
         }
+    });
 
-        MutexGuard { mutex: self }
-    }
-}
-
-unsafe impl<T> Sync for Mutex<T> where T: Send {}
-
-pub struct MutexGuard<'a, T> {
-    mutex: &'a Mutex<T>,
-}
-
-impl<T> Deref for MutexGuard<'_, T> {
-    type Target = T;
-
-    fn deref(&self) -> &T {
-        unsafe { &*self.mutex.value.get() }
-    }
-}
-
-impl<T> DerefMut for MutexGuard<'_, T> {
-    fn deref_mut(&mut self) -> &mut T {
-        unsafe { &mut *self.mutex.value.get() }
-    }
+    // every increment happened while holding the lock, so none got lost
+    println!("{}", *counter.lock());
 }
-
-impl<T> Drop for MutexGuard<'_, T> {
-    fn drop(&mut self) {
-        // Set the state back to 0: unlocked
-        self.mutex.state.store(0, Release);
-        // Wake up one of the waiting threads, if any.
-        wake_one(&self.mutex.state);
-    }
-}
-
-fn main() {}
@@ -0,0 +1,65 @@
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::Relaxed;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use threads_in_rust::thread_pool::{
+    ChannelQueue, LockFreeQueue, StdChannelQueue, ThreadPool, ThreadPoolBuilder,
+};
+
+const JOBS: usize = 1_000_000;
+
+static DONE: AtomicUsize = AtomicUsize::new(0);
+
+type MakeBuilder = fn() -> ThreadPoolBuilder;
+
+fn main() {
+    let backends: [(&str, MakeBuilder); 4] = [
+        ("priority queue (default)", ThreadPool::builder),
+        ("channel + futex mutex", || {
+            ThreadPool::builder().queue_backend(ChannelQueue::new())
+        }),
+        ("std::sync::mpsc", || {
+            ThreadPool::builder().queue_backend(StdChannelQueue::new())
+        }),
+        ("lock-free ring buffer", || {
+            ThreadPool::builder().queue_backend(LockFreeQueue::new())
+        }),
+    ];
+
+    for producers in [1, 4] {
+        println!("{JOBS} jobs from {producers} thread(s):");
+
+        for (name, builder) in backends {
+            let pool = builder().build().unwrap();
+            let elapsed = run(&pool, producers);
+            let per_second = JOBS as f64 / elapsed.as_secs_f64();
+
+            println!("  {name:<25} {elapsed:>10.2?} {per_second:>12.0} jobs/s");
+        }
+    }
+}
+
+// Time until all jobs are done, with the jobs doing as little as possible so
+// that only the queue is measured
+fn run(pool: &ThreadPool, producers: usize) -> Duration {
+    DONE.store(0, Relaxed);
+    let start = Instant::now();
+
+    thread::scope(|s| {
+        for _ in 0..producers {
+            s.spawn(|| {
+                for _ in 0..JOBS / producers {
+                    pool.execute(|| {
+                        DONE.fetch_add(1, Relaxed);
+                    });
+                }
+            });
+        }
+    });
+    pool.wait_idle();
+
+    let elapsed = start.elapsed();
+    assert_eq!(DONE.load(Relaxed), JOBS / producers * producers);
+    elapsed
+}
@@ -0,0 +1,167 @@
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering::{AcqRel, Acquire, Relaxed};
+use std::time::{Duration, Instant};
+
+use crate::deadline;
+use crate::futex::{wait, wait_timeout, wake_all};
+
+// The number of threads that arrived in the lower bits, the generation in
+// the upper ones. Both in one atomic, so a thread giving up can take back
+// its arrival only if the generation didn't move on in the meantime.
+const COUNT_BITS: u32 = 16;
+const COUNT_MASK: u32 = (1 << COUNT_BITS) - 1;
+
+/// Makes `n` threads wait for each other, e.g. between the phases of some
+/// work. Once the last one arrived, all of them continue and the barrier can
+/// be used again right away.
+pub struct Barrier {
+    n: u32,
+    state: AtomicU32,
+}
+
+/// Returned to every thread passing a [`Barrier`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BarrierWaitResult {
+    leader: bool,
+}
+
+impl BarrierWaitResult {
+    /// True for exactly one of the threads of every generation, the last one
+    /// to arrive
+    pub fn is_leader(&self) -> bool {
+        self.leader
+    }
+}
+
+fn generation(state: u32) -> u32 {
+    state >> COUNT_BITS
+}
+
+impl Barrier {
+    pub const fn new(n: u32) -> Self {
+        assert!(n > 0 && n <= COUNT_MASK, "unsupported number of threads");
+
+        Self {
+            n,
+            state: AtomicU32::new(0),
+        }
+    }
+
+    /// Blocks until all `n` threads called `wait`
+    pub fn wait(&self) -> BarrierWaitResult {
+        self.wait_until(None).unwrap()
+    }
+
+    /// Like [`wait`](Self::wait), but gives up after `timeout`, as if this
+    /// thread never arrived. Returns None if it gave up.
+    pub fn wait_timeout(&self, timeout: Duration) -> Option<BarrierWaitResult> {
+        self.wait_until(deadline::after(timeout))
+    }
+
+    fn wait_until(&self, deadline: Option<Instant>) -> Option<BarrierWaitResult> {
+        let mut state = self.state.load(Relaxed);
+
+        // arrive, the last one starts the next generation with no one there
+        let (leader, generation_arrived) = loop {
+            let leader = (state & COUNT_MASK) + 1 == self.n;
+            let new_state = if leader {
+                generation(state).wrapping_add(1) << COUNT_BITS
+            } else {
+                state + 1
+            };
+
+            // Release to pass on what was done before arriving, Acquire for
+            // the leader to see what the others did
+            match self
+                .state
+                .compare_exchange_weak(state, new_state, AcqRel, Relaxed)
+            {
+                Ok(_) => break (leader, generation(state)),
+                Err(actual) => state = actual,
+            }
+        };
+
+        if leader {
+            wake_all(&self.state);
+            return Some(BarrierWaitResult { leader: true });
+        }
+
+        loop {
+            let state = self.state.load(Acquire);
+            if generation(state) != generation_arrived {
+                return Some(BarrierWaitResult { leader: false });
+            }
+
+            match deadline {
+                None => wait(&self.state, state),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now < deadline {
+                        wait_timeout(&self.state, state, deadline - now);
+                    } else if self
+                        .state
+                        .compare_exchange(state, state - 1, Relaxed, Relaxed)
+                        .is_ok()
+                    {
+                        // took back the arrival before the others were done
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn all_threads_pass_together_every_generation() {
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+
+    const THREADS: usize = 8;
+    const GENERATIONS: usize = 1000;
+
+    let barrier = Barrier::new(THREADS as u32);
+    let arrived = AtomicUsize::new(0);
+    let leaders = AtomicUsize::new(0);
+
+    thread::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for generation in 0..GENERATIONS {
+                    arrived.fetch_add(1, Relaxed);
+
+                    if barrier.wait().is_leader() {
+                        leaders.fetch_add(1, Relaxed);
+                    }
+
+                    // nobody can be more than one generation ahead
+                    let arrived = arrived.load(Relaxed);
+                    assert!(arrived >= (generation + 1) * THREADS);
+                    assert!(arrived <= (generation + 2) * THREADS);
+                }
+            });
+        }
+    });
+
+    assert_eq!(leaders.load(Relaxed), GENERATIONS);
+}
+
+#[test]
+fn giving_up_takes_back_the_arrival() {
+    use std::thread;
+
+    let barrier = Barrier::new(2);
+
+    let start = Instant::now();
+    assert_eq!(barrier.wait_timeout(Duration::from_millis(20)), None);
+    assert!(start.elapsed() >= Duration::from_millis(20));
+
+    // otherwise, only one more thread would be needed now
+    assert_eq!(barrier.wait_timeout(Duration::from_millis(20)), None);
+
+    thread::scope(|s| {
+        let other = s.spawn(|| barrier.wait());
+        let this = barrier.wait_timeout(Duration::from_secs(5)).unwrap();
+        assert_ne!(this.is_leader(), other.join().unwrap().is_leader());
+    });
+}
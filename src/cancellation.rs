@@ -0,0 +1,197 @@
+use std::mem;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::{Acquire, Release};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::time::Duration;
+
+use crate::deadline;
+
+type Callback = Box<dyn FnOnce() + Send>;
+
+/// Like the `STOP` flag from 08-stop-flags.rs, but it can be cloned and
+/// handed around, cancelling it wakes up the threads waiting for it, and
+/// cancelling a token cancels all of its children too.
+#[derive(Clone)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    // checked without the lock, only ever goes from false to true
+    cancelled: AtomicBool,
+    state: Mutex<State>,
+    cancelled_changed: Condvar,
+}
+
+struct State {
+    callbacks: Vec<Callback>,
+    // A child token doesn't keep itself registered with its parent alive
+    children: Vec<Weak<Inner>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                cancelled: AtomicBool::new(false),
+                state: Mutex::new(State {
+                    callbacks: Vec::new(),
+                    children: Vec::new(),
+                }),
+                cancelled_changed: Condvar::new(),
+            }),
+        }
+    }
+
+    /// A new token that's cancelled together with this one, but can also be
+    /// cancelled on its own without affecting this one
+    pub fn child_token(&self) -> Self {
+        let child = Self::new();
+
+        let mut state = self.inner.state.lock().unwrap();
+        if self.is_cancelled() {
+            drop(state);
+            child.cancel();
+        } else {
+            state.children.retain(|child| child.strong_count() > 0);
+            state.children.push(Arc::downgrade(&child.inner));
+        }
+
+        child
+    }
+
+    /// Cancels this token and all of its children, runs the registered
+    /// callbacks and wakes up everyone waiting for it. Does nothing if the
+    /// token was cancelled already.
+    pub fn cancel(&self) {
+        let mut state = self.inner.state.lock().unwrap();
+        if self.inner.cancelled.swap(true, Release) {
+            return;
+        }
+
+        let callbacks = mem::take(&mut state.callbacks);
+        let children = mem::take(&mut state.children);
+        drop(state);
+
+        self.inner.cancelled_changed.notify_all();
+
+        // without holding the lock, callbacks might use the token themselves
+        for callback in callbacks {
+            callback();
+        }
+        for child in children {
+            if let Some(inner) = child.upgrade() {
+                CancellationToken { inner }.cancel();
+            }
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Acquire)
+    }
+
+    /// Runs `f` on the thread that cancels the token, or right away if it's
+    /// cancelled already
+    pub fn on_cancel<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let mut state = self.inner.state.lock().unwrap();
+        if self.is_cancelled() {
+            drop(state);
+            f();
+        } else {
+            state.callbacks.push(Box::new(f));
+        }
+    }
+
+    /// Blocks until the token is cancelled, or `timeout` passed. Returns
+    /// true if it was cancelled.
+    pub fn wait_cancelled(&self, timeout: Duration) -> bool {
+        if self.is_cancelled() {
+            return true;
+        }
+
+        let deadline = deadline::after(timeout);
+        let mut state = self.inner.state.lock().unwrap();
+
+        while !self.is_cancelled() {
+            let Some(timeout) = deadline::remaining(deadline) else {
+                return false;
+            };
+
+            state = self
+                .inner
+                .cancelled_changed
+                .wait_timeout(state, timeout)
+                .unwrap()
+                .0;
+        }
+
+        true
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn cancelling_a_parent_cancels_its_children() {
+    let parent = CancellationToken::new();
+    let child = parent.child_token();
+    let grandchild = child.child_token();
+    let sibling = parent.child_token();
+
+    // cancelling a child leaves the rest alone
+    sibling.cancel();
+    assert!(sibling.is_cancelled());
+    assert!(!parent.is_cancelled());
+    assert!(!child.is_cancelled());
+
+    parent.cancel();
+    assert!(child.is_cancelled());
+    assert!(grandchild.is_cancelled());
+
+    // children of cancelled tokens start out cancelled
+    assert!(parent.child_token().is_cancelled());
+}
+
+#[test]
+fn callbacks_and_waiters_are_notified() {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering::Relaxed;
+    use std::thread;
+
+    let token = CancellationToken::new();
+    let calls = Arc::new(AtomicUsize::new(0));
+
+    for _ in 0..3 {
+        let calls = Arc::clone(&calls);
+        token.on_cancel(move || {
+            calls.fetch_add(1, Relaxed);
+        });
+    }
+
+    assert!(!token.wait_cancelled(Duration::from_millis(10)));
+
+    thread::scope(|s| {
+        // too long to be added to now, so it waits without a timeout
+        let waiter = s.spawn(|| token.wait_cancelled(Duration::MAX));
+        thread::sleep(Duration::from_millis(10));
+        token.cancel();
+        assert!(waiter.join().unwrap());
+    });
+    assert!(token.wait_cancelled(Duration::MAX));
+
+    assert_eq!(calls.load(Relaxed), 3);
+
+    // callbacks registered afterwards run right away
+    let calls_after = Arc::clone(&calls);
+    token.on_cancel(move || {
+        calls_after.fetch_add(1, Relaxed);
+    });
+    assert_eq!(calls.load(Relaxed), 4);
+}
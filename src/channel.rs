@@ -0,0 +1,82 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::deadline;
+use crate::mutex::{Condvar, Mutex};
+
+/// A queue of messages that any number of threads can send to and receive
+/// from, on the futex based [`Mutex`] and [`Condvar`]. Receiving waits for a
+/// message, and can time out. See 14-channels.rs for it in use.
+pub struct Channel<T> {
+    queue: Mutex<VecDeque<T>>,
+    item_ready: Condvar,
+}
+
+impl<T> Channel<T> {
+    pub fn new() -> Self {
+        Self {
+            queue: Mutex::new(VecDeque::new()),
+            item_ready: Condvar::new(),
+        }
+    }
+
+    pub fn send(&self, message: T) {
+        self.queue.lock().push_back(message);
+        self.item_ready.notify_one();
+    }
+
+    pub fn receive(&self) -> T {
+        let mut b = self.queue.lock();
+
+        loop {
+            if let Some(message) = b.pop_front() {
+                return message;
+            }
+
+            b = self.item_ready.wait(b);
+        }
+    }
+
+    /// Like [`Channel::receive`], but returns None if there was no message
+    /// within `timeout`
+    pub fn receive_timeout(&self, timeout: Duration) -> Option<T> {
+        let deadline = deadline::after(timeout);
+        let mut b = self.queue.lock();
+
+        loop {
+            if let Some(message) = b.pop_front() {
+                return Some(message);
+            }
+
+            let timeout = deadline::remaining(deadline)?;
+            b = self.item_ready.wait_timeout(b, timeout).0;
+        }
+    }
+}
+
+impl<T> Default for Channel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn messages_are_received_in_order() {
+    use std::thread;
+
+    let channel = Channel::new();
+
+    thread::scope(|s| {
+        s.spawn(|| {
+            for i in 0..1000 {
+                channel.send(i);
+            }
+        });
+
+        for i in 0..1000 {
+            assert_eq!(channel.receive(), i);
+        }
+    });
+
+    assert_eq!(channel.receive_timeout(Duration::from_millis(10)), None);
+}
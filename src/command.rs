@@ -0,0 +1,270 @@
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use crate::cancellation::CancellationToken;
+
+type Handler = Box<dyn Fn(&[&str]) -> Result<String, String> + Send + Sync>;
+
+/// Commands to control background threads with, like `pause` or `set-rate
+/// 10`, read line by line from stdin or a Unix socket. The handlers run on
+/// the thread reading the commands, so they should only flip atomics or
+/// cancel tokens the background threads are watching, not wait for them.
+#[derive(Default)]
+pub struct Commands {
+    commands: BTreeMap<String, Command>,
+}
+
+struct Command {
+    usage: String,
+    help: String,
+    handler: Handler,
+}
+
+impl Commands {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a command, with `usage` being its name followed by its
+    /// arguments, e.g. `set-rate N`. The handler gets the arguments and
+    /// returns what to print, or why the command failed.
+    pub fn register<F>(&mut self, usage: &str, help: &str, handler: F) -> &mut Self
+    where
+        F: Fn(&[&str]) -> Result<String, String> + Send + Sync + 'static,
+    {
+        let name = usage.split_whitespace().next().expect("usage is empty");
+        assert!(name != "help", "help is built in");
+
+        self.commands.insert(
+            name.to_string(),
+            Command {
+                usage: usage.to_string(),
+                help: help.to_string(),
+                handler: Box::new(handler),
+            },
+        );
+        self
+    }
+
+    /// Runs a single line, returning what to print
+    pub fn execute(&self, line: &str) -> Result<String, String> {
+        let mut words = line.split_whitespace();
+        let Some(name) = words.next() else {
+            return Ok(String::new());
+        };
+        let args = Vec::from_iter(words);
+
+        if name == "help" {
+            return Ok(self.help());
+        }
+
+        match self.commands.get(name) {
+            Some(command) => (command.handler)(&args),
+            None => Err(format!("unknown command: {name:?}, try help")),
+        }
+    }
+
+    fn help(&self) -> String {
+        let width = self
+            .commands
+            .values()
+            .map(|command| command.usage.len())
+            .max()
+            .unwrap_or(0);
+
+        let lines = self
+            .commands
+            .values()
+            .map(|command| format!("{:<width$}  {}", command.usage, command.help));
+        Vec::from_iter(lines).join("\n")
+    }
+
+    /// Runs the commands read from `input` and writes their output to
+    /// `output`, until the input ends or `stop` is cancelled
+    pub fn serve(
+        &self,
+        input: impl BufRead,
+        mut output: impl Write,
+        stop: &CancellationToken,
+    ) -> io::Result<()> {
+        for line in input.lines() {
+            match self.execute(&line?) {
+                Ok(reply) if reply.is_empty() => {}
+                Ok(reply) => writeln!(output, "{reply}")?,
+                Err(error) => writeln!(output, "error: {error}")?,
+            }
+            output.flush()?;
+
+            if stop.is_cancelled() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Listens on a Unix socket at `path` until `stop` is cancelled, serving
+    /// every connection on a thread of its own, e.g. for `nc -U path`. Fails
+    /// if something other than a socket is at `path` already.
+    #[cfg(unix)]
+    pub fn serve_unix_socket(
+        self: Arc<Self>,
+        path: impl Into<std::path::PathBuf>,
+        stop: &CancellationToken,
+    ) -> io::Result<JoinHandle<()>> {
+        use std::collections::HashMap;
+        use std::net::Shutdown;
+        use std::os::unix::net::{UnixListener, UnixStream};
+        use std::sync::Mutex;
+
+        let path = path.into();
+        // left over from an earlier run
+        remove_socket(&path)?;
+        let listener = UnixListener::bind(&path)?;
+
+        // accept doesn't return on its own once stopped
+        stop.on_cancel({
+            let path = path.clone();
+            move || drop(UnixStream::connect(path))
+        });
+
+        // the open connections by id, shut down once stopped, as their
+        // threads are blocked reading the next command
+        let connections = Arc::new(Mutex::new(HashMap::new()));
+
+        let stop = stop.clone();
+        Ok(thread::spawn(move || {
+            for (id, stream) in listener.incoming().enumerate() {
+                if stop.is_cancelled() {
+                    break;
+                }
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                let (Ok(input), Ok(connection)) = (stream.try_clone(), stream.try_clone()) else {
+                    continue;
+                };
+                connections.lock().unwrap().insert(id, connection);
+
+                let commands = Arc::clone(&self);
+                let connections = Arc::clone(&connections);
+                let stop = stop.clone();
+                thread::spawn(move || {
+                    let _ = commands.serve(BufReader::new(input), stream, &stop);
+                    connections.lock().unwrap().remove(&id);
+                });
+            }
+
+            for (_, connection) in connections.lock().unwrap().drain() {
+                let _ = connection.shutdown(Shutdown::Both);
+            }
+            let _ = remove_socket(&path);
+        }))
+    }
+}
+
+// Removes the socket at `path`, but nothing else that might be there
+#[cfg(unix)]
+fn remove_socket(path: &std::path::Path) -> io::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path),
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists and isn't a socket", path.display()),
+        )),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+#[test]
+fn commands_are_parsed_and_dispatched() {
+    use std::sync::atomic::AtomicU32;
+    use std::sync::atomic::Ordering::Relaxed;
+
+    let rate = Arc::new(AtomicU32::new(1));
+    let stop = CancellationToken::new();
+
+    let mut commands = Commands::new();
+    commands
+        .register("set-rate N", "changes the rate", {
+            let rate = Arc::clone(&rate);
+            move |args| {
+                let [n] = args else {
+                    return Err("expected a single rate".into());
+                };
+                let n = n.parse().map_err(|_| format!("not a number: {n}"))?;
+                rate.store(n, Relaxed);
+                Ok(String::new())
+            }
+        })
+        .register("stop", "stops", {
+            let stop = stop.clone();
+            move |_| {
+                stop.cancel();
+                Ok("stopping".into())
+            }
+        });
+
+    assert_eq!(
+        commands.execute("help").unwrap(),
+        "set-rate N  changes the rate\nstop        stops"
+    );
+    assert_eq!(commands.execute("   "), Ok(String::new()));
+    assert!(commands.execute("set-rate").is_err());
+    assert!(commands.execute("jump").is_err());
+
+    // lines after stop aren't run anymore
+    let input = "set-rate 5\nset-rate x\nstop\nset-rate 7\n";
+    let mut output = Vec::new();
+    commands
+        .serve(input.as_bytes(), &mut output, &stop)
+        .unwrap();
+
+    assert_eq!(rate.load(Relaxed), 5);
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "error: not a number: x\nstopping\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn commands_are_served_on_a_unix_socket() {
+    use std::io::Read;
+    use std::os::unix::net::UnixStream;
+
+    let mut commands = Commands::new();
+    commands.register("ping", "answers", |_| Ok("pong".into()));
+
+    let path = std::env::temp_dir().join(format!("commands-{}.sock", std::process::id()));
+    let stop = CancellationToken::new();
+    let server = Arc::new(commands).serve_unix_socket(&path, &stop).unwrap();
+
+    let mut stream = UnixStream::connect(&path).unwrap();
+    stream.write_all(b"ping\n").unwrap();
+    let mut reply = String::new();
+    BufReader::new(&stream).read_line(&mut reply).unwrap();
+    assert_eq!(reply, "pong\n");
+
+    // the connection is closed too, even though it's still waiting for
+    // the next command
+    stop.cancel();
+    server.join().unwrap();
+    assert!(!path.exists());
+    assert_eq!(stream.read(&mut [0; 1]).unwrap(), 0);
+
+    // anything but a socket is left alone
+    std::fs::write(&path, "not a socket").unwrap();
+    let commands = Arc::new(Commands::new());
+    let error = commands
+        .serve_unix_socket(&path, &CancellationToken::new())
+        .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "not a socket");
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,29 @@
+use std::time::{Duration, Instant};
+
+/// The time `timeout` from now, or None if that's too far away to be
+/// represented, which callers treat as waiting forever
+pub fn after(timeout: Duration) -> Option<Instant> {
+    Instant::now().checked_add(timeout)
+}
+
+/// How long is left until `deadline`, or None once it has passed. Without a
+/// deadline, there's always `Duration::MAX` left.
+pub fn remaining(deadline: Option<Instant>) -> Option<Duration> {
+    match deadline {
+        None => Some(Duration::MAX),
+        Some(deadline) => {
+            let now = Instant::now();
+            (now < deadline).then(|| deadline - now)
+        }
+    }
+}
+
+#[test]
+fn long_timeouts_have_no_deadline() {
+    assert_eq!(after(Duration::MAX), None);
+    assert_eq!(remaining(None), Some(Duration::MAX));
+
+    let deadline = after(Duration::from_secs(60));
+    assert!(remaining(deadline).unwrap() <= Duration::from_secs(60));
+    assert_eq!(remaining(after(Duration::ZERO)), None);
+}
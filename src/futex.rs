@@ -0,0 +1,55 @@
+use std::sync::atomic::AtomicU32;
+use std::time::Duration;
+
+pub use atomic_wait::{wait, wake_all, wake_one};
+
+/// Like `atomic_wait::wait`, but gives up after `timeout`. Just like `wait`,
+/// this may also return spuriously, so the caller has to check the value
+/// again either way.
+#[cfg(target_os = "linux")]
+pub fn wait_timeout(atomic: &AtomicU32, expected: u32, timeout: Duration) {
+    let timeout = libc::timespec {
+        tv_sec: timeout.as_secs().min(libc::time_t::MAX as u64) as libc::time_t,
+        tv_nsec: timeout.subsec_nanos() as libc::c_long,
+    };
+
+    // same operation as atomic_wait uses, with a relative timeout added
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            atomic,
+            libc::FUTEX_WAIT | libc::FUTEX_PRIVATE_FLAG,
+            expected,
+            &timeout as *const libc::timespec,
+        );
+    }
+}
+
+/// Like `atomic_wait::wait`, but gives up after `timeout`. Without a futex
+/// to wait on, this just sleeps for a bit and lets the caller check again.
+#[cfg(not(target_os = "linux"))]
+pub fn wait_timeout(atomic: &AtomicU32, expected: u32, timeout: Duration) {
+    use std::sync::atomic::Ordering::Relaxed;
+
+    if atomic.load(Relaxed) == expected {
+        std::thread::sleep(timeout.min(Duration::from_millis(1)));
+    }
+}
+
+#[test]
+fn wait_timeout_returns_after_the_timeout() {
+    use std::time::Instant;
+
+    let atomic = AtomicU32::new(0);
+    let start = Instant::now();
+
+    // spurious wakeups are allowed, so wait until the timeout passed for real
+    while start.elapsed() < Duration::from_millis(20) {
+        wait_timeout(&atomic, 0, Duration::from_millis(20) - start.elapsed());
+    }
+
+    // doesn't wait at all if the value doesn't match
+    let start = Instant::now();
+    wait_timeout(&atomic, 1, Duration::from_secs(5));
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
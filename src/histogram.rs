@@ -0,0 +1,240 @@
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering::Relaxed;
+
+// Every power of two is split into 16 buckets, so a bucket is never more than
+// 1/16th (6.25%) wider than the values in it. Values below 16 get a bucket of
+// their own.
+const SUB_BITS: u32 = 4;
+const SUB_BUCKETS: usize = 1 << SUB_BITS;
+const NUM_BUCKETS: usize = (64 - SUB_BITS as usize + 1) * SUB_BUCKETS;
+
+/// Counts values, like latencies in microseconds, in logarithmic buckets.
+/// Recording is a single relaxed `fetch_add`, so any number of threads can
+/// record without waiting for each other, and percentiles come out with a
+/// relative error of at most 6.25%.
+pub struct AtomicHistogram {
+    buckets: Box<[AtomicU64]>,
+    max: AtomicU64,
+}
+
+/// A copy of the counts of an [`AtomicHistogram`] at one point in time
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Histogram {
+    buckets: Vec<u64>,
+    count: u64,
+    max: u64,
+}
+
+fn bucket_index(value: u64) -> usize {
+    if value < SUB_BUCKETS as u64 {
+        return value as usize;
+    }
+
+    // the highest bit picks the power of two, the next SUB_BITS bits the
+    // bucket within it
+    let exponent = 63 - value.leading_zeros();
+    let shift = exponent - SUB_BITS;
+    let sub_bucket = (value >> shift) as usize & (SUB_BUCKETS - 1);
+
+    (exponent - SUB_BITS + 1) as usize * SUB_BUCKETS + sub_bucket
+}
+
+// The lowest and highest value that end up in the bucket
+fn bucket_range(index: usize) -> (u64, u64) {
+    if index < SUB_BUCKETS {
+        return (index as u64, index as u64);
+    }
+
+    let shift = (index / SUB_BUCKETS - 1) as u32;
+    let sub_bucket = (SUB_BUCKETS + index % SUB_BUCKETS) as u64;
+    let low = sub_bucket << shift;
+    let high = low + ((1 << shift) - 1);
+
+    (low, high)
+}
+
+impl AtomicHistogram {
+    pub fn new() -> Self {
+        Self {
+            buckets: Box::from_iter((0..NUM_BUCKETS).map(|_| AtomicU64::new(0))),
+            max: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, value: u64) {
+        self.buckets[bucket_index(value)].fetch_add(1, Relaxed);
+        self.max.fetch_max(value, Relaxed);
+    }
+
+    /// Adds all values recorded in `other` to this histogram
+    pub fn merge(&self, other: &AtomicHistogram) {
+        for (bucket, other) in self.buckets.iter().zip(other.buckets.iter()) {
+            let count = other.load(Relaxed);
+            if count > 0 {
+                bucket.fetch_add(count, Relaxed);
+            }
+        }
+        self.max.fetch_max(other.max.load(Relaxed), Relaxed);
+    }
+
+    /// Copies the current counts. Values recorded while copying might or
+    /// might not be included.
+    pub fn snapshot(&self) -> Histogram {
+        Histogram::new(
+            Vec::from_iter(self.buckets.iter().map(|bucket| bucket.load(Relaxed))),
+            self.max.load(Relaxed),
+        )
+    }
+
+    /// Empties the histogram, returning what was in it. Every value recorded
+    /// at the same time ends up either in the returned copy, or stays in the
+    /// histogram, so none get lost.
+    pub fn reset(&self) -> Histogram {
+        let max = self.max.swap(0, Relaxed);
+        Histogram::new(
+            Vec::from_iter(self.buckets.iter().map(|bucket| bucket.swap(0, Relaxed))),
+            max,
+        )
+    }
+}
+
+impl Default for AtomicHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Histogram {
+    fn new(buckets: Vec<u64>, max: u64) -> Self {
+        Self {
+            count: buckets.iter().sum(),
+            buckets,
+            max,
+        }
+    }
+
+    /// Number of values recorded
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    /// The value that `percentile` percent of the recorded values are less
+    /// than or equal to, e.g. 99.9 for p999. Returns None if there are no
+    /// values.
+    pub fn percentile(&self, percentile: f64) -> Option<u64> {
+        assert!((0.0..=100.0).contains(&percentile));
+
+        if self.count == 0 {
+            return None;
+        }
+
+        // the rank of the value we're looking for, counting from 1
+        let rank = ((percentile / 100.0 * self.count as f64).ceil() as u64).max(1);
+
+        let mut seen = 0;
+        for (index, &count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                // every value in the bucket is reported as its highest one,
+                // but never higher than the highest value recorded
+                let (low, high) = bucket_range(index);
+                return Some(high.min(self.max.max(low)));
+            }
+        }
+
+        unreachable!("the buckets add up to count");
+    }
+
+    pub fn p50(&self) -> Option<u64> {
+        self.percentile(50.0)
+    }
+
+    pub fn p99(&self) -> Option<u64> {
+        self.percentile(99.0)
+    }
+
+    pub fn p999(&self) -> Option<u64> {
+        self.percentile(99.9)
+    }
+}
+
+#[test]
+fn buckets_cover_all_values() {
+    for value in (0..100_000).chain([u64::MAX / 3, u64::MAX - 1, u64::MAX]) {
+        let (low, high) = bucket_range(bucket_index(value));
+        assert!(low <= value && value <= high, "{value}: {low}..={high}");
+        assert!(
+            high - low <= low / SUB_BUCKETS as u64,
+            "{value}: {low}..={high}"
+        );
+    }
+
+    assert_eq!(bucket_index(u64::MAX), NUM_BUCKETS - 1);
+}
+
+#[test]
+fn percentiles_are_within_the_precision() {
+    let histogram = AtomicHistogram::new();
+    assert_eq!(histogram.snapshot().p50(), None);
+
+    for value in 1..=10_000 {
+        histogram.record(value);
+    }
+
+    let snapshot = histogram.snapshot();
+    assert_eq!(snapshot.count(), 10_000);
+    assert_eq!(snapshot.max(), 10_000);
+    assert_eq!(snapshot.percentile(100.0), Some(10_000));
+
+    for (percentile, expected) in [(50.0, 5_000), (99.0, 9_900), (99.9, 9_990)] {
+        let value = snapshot.percentile(percentile).unwrap();
+        assert!(value >= expected, "p{percentile}: {value}");
+        assert!(value <= expected + expected / 16, "p{percentile}: {value}");
+    }
+}
+
+#[test]
+fn histograms_can_be_merged() {
+    let a = AtomicHistogram::new();
+    let b = AtomicHistogram::new();
+
+    for _ in 0..99 {
+        a.record(10);
+    }
+    b.record(1_000);
+
+    a.merge(&b);
+    let snapshot = a.snapshot();
+    assert_eq!(snapshot.count(), 100);
+    assert_eq!(snapshot.p99(), Some(10));
+    assert_eq!(snapshot.percentile(100.0), Some(1_000));
+}
+
+#[test]
+fn reset_while_recording_loses_nothing() {
+    use std::thread;
+
+    let histogram = AtomicHistogram::new();
+    let mut reset_count = 0;
+
+    thread::scope(|s| {
+        let writers = Vec::from_iter((0..4).map(|_| {
+            s.spawn(|| {
+                for value in 0..100_000 {
+                    histogram.record(value);
+                }
+            })
+        }));
+
+        while writers.iter().any(|writer| !writer.is_finished()) {
+            reset_count += histogram.reset().count();
+        }
+    });
+
+    assert_eq!(reset_count + histogram.reset().count(), 400_000);
+    assert_eq!(histogram.snapshot().count(), 0);
+}
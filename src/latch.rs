@@ -0,0 +1,104 @@
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::time::Duration;
+
+use crate::deadline;
+use crate::futex::{wait, wait_timeout, wake_all};
+
+/// Lets threads wait until [`count_down`](Self::count_down) was called a
+/// given number of times, e.g. until all workers are done starting up.
+/// Unlike a [`Barrier`](crate::barrier::Barrier), the threads counting
+/// down don't wait, and it can't be used again once it reached zero.
+pub struct CountDownLatch {
+    count: AtomicU32,
+}
+
+impl CountDownLatch {
+    pub const fn new(count: u32) -> Self {
+        Self {
+            count: AtomicU32::new(count),
+        }
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count.load(Relaxed)
+    }
+
+    /// Counts down by one, waking up the waiting threads once it reaches
+    /// zero. Does nothing if it's zero already.
+    pub fn count_down(&self) {
+        // Release, so the waiting threads see what was done before
+        let previous = self
+            .count
+            .fetch_update(Release, Relaxed, |count| count.checked_sub(1));
+
+        if previous == Ok(1) {
+            wake_all(&self.count);
+        }
+    }
+
+    /// Blocks until the count reached zero
+    pub fn wait(&self) {
+        loop {
+            let count = self.count.load(Acquire);
+            if count == 0 {
+                return;
+            }
+            wait(&self.count, count);
+        }
+    }
+
+    /// Like [`wait`](Self::wait), but gives up after `timeout`. Returns
+    /// true if the count reached zero.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let deadline = deadline::after(timeout);
+
+        loop {
+            let count = self.count.load(Acquire);
+            if count == 0 {
+                return true;
+            }
+
+            let Some(timeout) = deadline::remaining(deadline) else {
+                return false;
+            };
+            wait_timeout(&self.count, count, timeout);
+        }
+    }
+}
+
+#[test]
+fn waiters_are_released_at_zero() {
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+
+    let latch = CountDownLatch::new(16);
+    let ready = AtomicUsize::new(0);
+
+    assert!(!latch.wait_timeout(Duration::from_millis(10)));
+
+    thread::scope(|s| {
+        for _ in 0..8 {
+            s.spawn(|| {
+                latch.wait();
+                // everything done before counting down is visible
+                assert_eq!(ready.load(Relaxed), 16);
+            });
+        }
+
+        for _ in 0..16 {
+            s.spawn(|| {
+                ready.fetch_add(1, Relaxed);
+                latch.count_down();
+            });
+        }
+
+        assert!(latch.wait_timeout(Duration::from_secs(5)));
+    });
+
+    // it stays at zero
+    latch.count_down();
+    assert_eq!(latch.count(), 0);
+    latch.wait();
+    assert!(latch.wait_timeout(Duration::MAX));
+}
@@ -0,0 +1,19 @@
+pub mod barrier;
+pub mod cancellation;
+pub mod channel;
+pub mod command;
+mod deadline;
+pub mod futex;
+pub mod histogram;
+pub mod latch;
+pub mod mutex;
+pub mod once;
+pub mod parker;
+pub mod parking_lot;
+pub mod progress;
+pub mod semaphore;
+pub mod seqlock;
+#[cfg(unix)]
+pub mod signal;
+pub mod thread_pool;
+pub mod wait_group;
@@ -0,0 +1,173 @@
+use std::cell::UnsafeCell;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::sync::atomic::{AtomicU32, AtomicUsize};
+use std::time::Duration;
+
+use crate::deadline;
+use crate::futex::{wait, wait_timeout, wake_all, wake_one};
+
+/// A Mutex on a futex, see 19-mutex.rs for it in use
+pub struct Mutex<T> {
+    /// 0: unlocked
+    /// 1: locked
+    state: AtomicU32,
+    value: UnsafeCell<T>,
+}
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: AtomicU32::new(0), // initial unlocked state
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        // Set the state to 1: locked
+        while self.state.swap(1, Acquire) == 1 {
+            // If it was already locked
+            // wait unless the state is no longer 1
+            wait(&self.state, 1);
+        }
+
+        MutexGuard { mutex: self }
+    }
+}
+
+unsafe impl<T> Sync for Mutex<T> where T: Send {}
+
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        // Set the state back to 0: unlocked
+        self.mutex.state.store(0, Release);
+        // Wake up one of the waiting threads, if any.
+        wake_one(&self.mutex.state);
+    }
+}
+
+/// A condition variable for the [`Mutex`] above
+pub struct Condvar {
+    // changed on every notification, so waiters that already read it and
+    // unlocked the mutex don't miss one
+    counter: AtomicU32,
+    // to skip the syscall if nobody is waiting
+    num_waiters: AtomicUsize,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            counter: AtomicU32::new(0),
+            num_waiters: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn notify_one(&self) {
+        if self.num_waiters.load(Relaxed) > 0 {
+            self.counter.fetch_add(1, Relaxed);
+            wake_one(&self.counter);
+        }
+    }
+
+    pub fn notify_all(&self) {
+        if self.num_waiters.load(Relaxed) > 0 {
+            self.counter.fetch_add(1, Relaxed);
+            wake_all(&self.counter);
+        }
+    }
+
+    /// Unlocks the mutex while waiting for a notification. Might also
+    /// return spuriously.
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        self.num_waiters.fetch_add(1, Relaxed);
+
+        // read the counter before unlocking, so a notification sent after
+        // unlocking changes it and the wait returns right away
+        let counter_value = self.counter.load(Relaxed);
+        let mutex = guard.mutex;
+        drop(guard);
+
+        wait(&self.counter, counter_value);
+
+        self.num_waiters.fetch_sub(1, Relaxed);
+        mutex.lock()
+    }
+
+    /// Like [`Condvar::wait`], but gives up after `timeout`. Returns true if
+    /// it timed out.
+    pub fn wait_timeout<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        timeout: Duration,
+    ) -> (MutexGuard<'a, T>, bool) {
+        let deadline = deadline::after(timeout);
+        self.num_waiters.fetch_add(1, Relaxed);
+
+        let counter_value = self.counter.load(Relaxed);
+        let mutex = guard.mutex;
+        drop(guard);
+
+        wait_timeout(&self.counter, counter_value, timeout);
+
+        self.num_waiters.fetch_sub(1, Relaxed);
+        let timed_out = deadline::remaining(deadline).is_none();
+        (mutex.lock(), timed_out)
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn condvar_wakes_up_waiting_threads() {
+    use std::thread;
+
+    let mutex = Mutex::new(0);
+    let condvar = Condvar::new();
+    let mut wakeups = 0;
+
+    thread::scope(|s| {
+        s.spawn(|| {
+            thread::sleep(Duration::from_millis(10));
+            *mutex.lock() = 123;
+            condvar.notify_one();
+        });
+
+        let mut m = mutex.lock();
+        while *m < 100 {
+            m = condvar.wait(m);
+            wakeups += 1;
+        }
+
+        assert_eq!(*m, 123);
+    });
+
+    // the waiting thread really waited, instead of spinning on the mutex
+    assert!(wakeups < 10);
+
+    let (guard, timed_out) = condvar.wait_timeout(mutex.lock(), Duration::from_millis(10));
+    assert!(timed_out);
+    assert_eq!(*guard, 123);
+}
@@ -0,0 +1,354 @@
+use std::cell::{Cell, UnsafeCell};
+use std::fmt;
+use std::mem::{ManuallyDrop, MaybeUninit};
+use std::ops::Deref;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+
+use crate::futex::{wait, wake_all};
+
+mod race;
+
+pub use race::{OnceBool, OnceBox, OnceNonZeroUsize};
+
+const INCOMPLETE: u32 = 0;
+// an initializer panicked
+const POISONED: u32 = 1;
+const RUNNING: u32 = 2;
+// running, with other threads waiting for it
+const QUEUED: u32 = 3;
+const COMPLETE: u32 = 4;
+
+/// Runs a function exactly once, even if many threads try at the same time:
+/// the first one runs it, the others wait for it to finish. If it panics,
+/// the `Once` is poisoned.
+pub struct Once {
+    state: AtomicU32,
+}
+
+/// Passed to the function given to [`Once::call_once_force`]
+pub struct OnceState {
+    poisoned: bool,
+}
+
+impl OnceState {
+    /// Whether an earlier call panicked
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+}
+
+// Sets the state once the function returned, or panicked, and wakes up the
+// threads waiting for it
+struct CompletionGuard<'a> {
+    state: &'a AtomicU32,
+    set_to: u32,
+}
+
+impl Drop for CompletionGuard<'_> {
+    fn drop(&mut self) {
+        if self.state.swap(self.set_to, Release) == QUEUED {
+            wake_all(self.state);
+        }
+    }
+}
+
+impl Once {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU32::new(INCOMPLETE),
+        }
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Acquire) == COMPLETE
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.state.load(Relaxed) == POISONED
+    }
+
+    /// Runs `f` if no call before completed, otherwise waits for the one
+    /// that's running or returns right away.
+    ///
+    /// Panics if an earlier call panicked.
+    pub fn call_once<F: FnOnce()>(&self, f: F) {
+        if self.is_completed() {
+            return;
+        }
+
+        let mut f = Some(f);
+        self.call(false, &mut |_| f.take().unwrap()());
+    }
+
+    /// Like [`call_once`](Self::call_once), but also runs `f` if an earlier
+    /// call panicked, which it can find out from the [`OnceState`]
+    pub fn call_once_force<F: FnOnce(&OnceState)>(&self, f: F) {
+        if self.is_completed() {
+            return;
+        }
+
+        let mut f = Some(f);
+        self.call(true, &mut |state| f.take().unwrap()(state));
+    }
+
+    // Not generic, so it's only compiled once
+    fn call(&self, ignore_poison: bool, f: &mut dyn FnMut(&OnceState)) {
+        let mut state = self.state.load(Acquire);
+
+        loop {
+            match state {
+                COMPLETE => return,
+                POISONED if !ignore_poison => {
+                    panic!("Once instance has previously been poisoned")
+                }
+                INCOMPLETE | POISONED => {
+                    if let Err(actual) = self
+                        .state
+                        .compare_exchange_weak(state, RUNNING, Acquire, Acquire)
+                    {
+                        state = actual;
+                        continue;
+                    }
+
+                    // poisons the Once if f panics
+                    let mut guard = CompletionGuard {
+                        state: &self.state,
+                        set_to: POISONED,
+                    };
+                    f(&OnceState {
+                        poisoned: state == POISONED,
+                    });
+                    guard.set_to = COMPLETE;
+                    return;
+                }
+                RUNNING | QUEUED => {
+                    // let the running thread know it has to wake us up
+                    if state == RUNNING {
+                        if let Err(actual) = self
+                            .state
+                            .compare_exchange_weak(RUNNING, QUEUED, Relaxed, Acquire)
+                        {
+                            state = actual;
+                            continue;
+                        }
+                    }
+
+                    wait(&self.state, QUEUED);
+                    state = self.state.load(Acquire);
+                }
+                _ => unreachable!("invalid state {state}"),
+            }
+        }
+    }
+}
+
+impl Default for Once {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A value that's set at most once, by whichever thread gets there first,
+/// and never changes afterwards
+pub struct OnceLock<T> {
+    once: Once,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send> Send for OnceLock<T> {}
+// sharing it lets other threads set the value, and read it
+unsafe impl<T: Send + Sync> Sync for OnceLock<T> {}
+
+impl<T> OnceLock<T> {
+    pub const fn new() -> Self {
+        Self {
+            once: Once::new(),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// The value, or None if it's not set yet
+    pub fn get(&self) -> Option<&T> {
+        // the Acquire in is_completed makes the value visible
+        if self.once.is_completed() {
+            Some(unsafe { (*self.value.get()).assume_init_ref() })
+        } else {
+            None
+        }
+    }
+
+    /// The value, set to the result of `f` first if it's not set yet. If
+    /// another thread is running its `f` already, this waits for it instead.
+    ///
+    /// If `f` panics, the panic is passed on and the value is left unset, so
+    /// the next call tries again.
+    pub fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &T {
+        if let Some(value) = self.get() {
+            return value;
+        }
+
+        self.once.call_once_force(|_| unsafe {
+            (*self.value.get()).write(f());
+        });
+
+        self.get().unwrap()
+    }
+
+    /// Sets the value, or returns `value` if it's set already
+    pub fn set(&self, value: T) -> Result<(), T> {
+        let mut value = Some(value);
+        self.get_or_init(|| value.take().unwrap());
+
+        match value {
+            None => Ok(()),
+            Some(value) => Err(value),
+        }
+    }
+
+    pub fn into_inner(self) -> Option<T> {
+        // the value is moved out, so it mustn't be dropped again
+        let this = ManuallyDrop::new(self);
+
+        if this.once.is_completed() {
+            Some(unsafe { (*this.value.get()).assume_init_read() })
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> Drop for OnceLock<T> {
+    fn drop(&mut self) {
+        if self.once.is_completed() {
+            unsafe { self.value.get_mut().assume_init_drop() };
+        }
+    }
+}
+
+impl<T> Default for OnceLock<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for OnceLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.get() {
+            Some(value) => f.debug_tuple("OnceLock").field(value).finish(),
+            None => f.write_str("OnceLock(<unset>)"),
+        }
+    }
+}
+
+/// A value that's initialized by `F` the first time it's used
+pub struct Lazy<T, F = fn() -> T> {
+    cell: OnceLock<T>,
+    // only taken by the thread running the initialization
+    init: Cell<Option<F>>,
+}
+
+unsafe impl<T: Send + Sync, F: Send> Sync for Lazy<T, F> {}
+
+impl<T, F: FnOnce() -> T> Lazy<T, F> {
+    pub const fn new(init: F) -> Self {
+        Self {
+            cell: OnceLock::new(),
+            init: Cell::new(Some(init)),
+        }
+    }
+
+    /// Initializes the value if it isn't yet, like dereferencing it does
+    pub fn force(this: &Self) -> &T {
+        this.cell.get_or_init(|| match this.init.take() {
+            Some(init) => init(),
+            None => panic!("Lazy instance has previously been poisoned"),
+        })
+    }
+}
+
+impl<T, F: FnOnce() -> T> Deref for Lazy<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        Lazy::force(self)
+    }
+}
+
+#[test]
+fn once_runs_once_and_is_poisoned_by_panics() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let once = Once::new();
+    let mut calls = 0;
+    once.call_once(|| calls += 1);
+    once.call_once(|| calls += 1);
+    assert_eq!(calls, 1);
+    assert!(once.is_completed());
+
+    let once = Once::new();
+    assert!(catch_unwind(|| once.call_once(|| panic!("failed"))).is_err());
+    assert!(once.is_poisoned());
+    assert!(catch_unwind(AssertUnwindSafe(|| once.call_once(|| calls += 1))).is_err());
+    assert_eq!(calls, 1);
+
+    // forcing it runs it again, which can clear the poison
+    once.call_once_force(|state| {
+        assert!(state.is_poisoned());
+        calls += 1;
+    });
+    assert_eq!(calls, 2);
+    assert!(once.is_completed());
+}
+
+#[test]
+fn concurrent_initializers_wait_for_the_first() {
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+    use std::time::Duration;
+
+    let cell = OnceLock::new();
+    let calls = AtomicUsize::new(0);
+
+    thread::scope(|s| {
+        let threads = Vec::from_iter((0..8).map(|i| {
+            let (cell, calls) = (&cell, &calls);
+            s.spawn(move || {
+                *cell.get_or_init(|| {
+                    calls.fetch_add(1, Relaxed);
+                    thread::sleep(Duration::from_millis(50));
+                    i
+                })
+            })
+        }));
+
+        let values = Vec::from_iter(threads.into_iter().map(|t| t.join().unwrap()));
+        assert!(values.iter().all(|&value| value == values[0]));
+    });
+
+    assert_eq!(calls.load(Relaxed), 1);
+    assert_eq!(cell.set(100), Err(100));
+    assert!(cell.into_inner().is_some());
+}
+
+#[test]
+fn panicking_initializers_leave_the_value_unset() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::sync::atomic::AtomicUsize;
+
+    let cell = OnceLock::<String>::new();
+    let result = catch_unwind(AssertUnwindSafe(|| cell.get_or_init(|| panic!("failed"))));
+    assert!(result.is_err());
+    assert_eq!(cell.get(), None);
+    assert_eq!(cell.set("set".into()), Ok(()));
+    assert_eq!(cell.get().unwrap(), "set");
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+    static LAZY: Lazy<Vec<usize>> = Lazy::new(|| {
+        CALLS.fetch_add(1, Relaxed);
+        vec![1, 2, 3]
+    });
+    assert_eq!(LAZY.len(), 3);
+    assert_eq!(*LAZY, [1, 2, 3]);
+    assert_eq!(CALLS.load(Relaxed), 1);
+}
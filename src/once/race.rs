@@ -0,0 +1,218 @@
+use std::num::NonZeroUsize;
+use std::ptr;
+use std::sync::atomic::Ordering::{Acquire, Release};
+use std::sync::atomic::{AtomicPtr, AtomicUsize};
+
+// Unlike the cells in mod.rs, nothing here ever blocks: threads that find
+// the cell empty all compute a value, and the first to publish its own with a
+// compare_exchange wins. Everyone else gets the winner's value.
+
+/// A boxed value set at most once, without ever waiting for another thread.
+/// Threads losing the race to initialize it drop their box.
+pub struct OnceBox<T> {
+    ptr: AtomicPtr<T>,
+}
+
+// it owns the box and hands out references to it
+unsafe impl<T: Send> Send for OnceBox<T> {}
+unsafe impl<T: Send + Sync> Sync for OnceBox<T> {}
+
+impl<T> OnceBox<T> {
+    pub const fn new() -> Self {
+        Self {
+            ptr: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        // Acquire to see the value the pointer was published with
+        let ptr = self.ptr.load(Acquire);
+        unsafe { ptr.as_ref() }
+    }
+
+    /// Sets the value, or returns `value` if it's set already
+    pub fn set(&self, value: Box<T>) -> Result<(), Box<T>> {
+        let ptr = Box::into_raw(value);
+
+        match self
+            .ptr
+            .compare_exchange(ptr::null_mut(), ptr, Release, Acquire)
+        {
+            Ok(_) => Ok(()),
+            Err(_) => Err(unsafe { Box::from_raw(ptr) }),
+        }
+    }
+
+    /// The value, set to the result of `f` first if it's not set yet. If
+    /// several threads get here at once, all of them run `f`, but only one
+    /// result is kept.
+    pub fn get_or_init<F: FnOnce() -> Box<T>>(&self, f: F) -> &T {
+        if let Some(value) = self.get() {
+            return value;
+        }
+
+        // losing the race drops our box
+        let _ = self.set(f());
+        self.get().unwrap()
+    }
+}
+
+impl<T> Drop for OnceBox<T> {
+    fn drop(&mut self) {
+        let ptr = *self.ptr.get_mut();
+        if !ptr.is_null() {
+            drop(unsafe { Box::from_raw(ptr) });
+        }
+    }
+}
+
+impl<T> Default for OnceBox<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A number set at most once, without ever waiting for another thread, with
+/// 0 meaning it's not set yet
+pub struct OnceNonZeroUsize {
+    value: AtomicUsize,
+}
+
+impl OnceNonZeroUsize {
+    pub const fn new() -> Self {
+        Self {
+            value: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn get(&self) -> Option<NonZeroUsize> {
+        NonZeroUsize::new(self.value.load(Acquire))
+    }
+
+    /// Sets the number, or returns the one that's set already
+    pub fn set(&self, value: NonZeroUsize) -> Result<(), NonZeroUsize> {
+        match self
+            .value
+            .compare_exchange(0, value.get(), Release, Acquire)
+        {
+            Ok(_) => Ok(()),
+            Err(actual) => Err(NonZeroUsize::new(actual).unwrap()),
+        }
+    }
+
+    /// The number, set to the result of `f` first if it's not set yet. `f`
+    /// might run on several threads at once, but they all get the same
+    /// number back.
+    pub fn get_or_init<F: FnOnce() -> NonZeroUsize>(&self, f: F) -> NonZeroUsize {
+        if let Some(value) = self.get() {
+            return value;
+        }
+
+        let value = f();
+        match self.set(value) {
+            Ok(()) => value,
+            Err(winner) => winner,
+        }
+    }
+}
+
+impl Default for OnceNonZeroUsize {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A bool set at most once, without ever waiting for another thread, like
+/// whether the CPU supports some instructions
+#[derive(Default)]
+pub struct OnceBool {
+    // 1: true, 2: false
+    inner: OnceNonZeroUsize,
+}
+
+impl OnceBool {
+    pub const fn new() -> Self {
+        Self {
+            inner: OnceNonZeroUsize::new(),
+        }
+    }
+
+    pub fn get(&self) -> Option<bool> {
+        self.inner.get().map(from_usize)
+    }
+
+    /// Sets the bool, or returns the one that's set already
+    pub fn set(&self, value: bool) -> Result<(), bool> {
+        self.inner.set(to_usize(value)).map_err(from_usize)
+    }
+
+    /// The bool, set to the result of `f` first if it's not set yet, see
+    /// [`OnceNonZeroUsize::get_or_init`]
+    pub fn get_or_init<F: FnOnce() -> bool>(&self, f: F) -> bool {
+        from_usize(self.inner.get_or_init(|| to_usize(f())))
+    }
+}
+
+fn to_usize(value: bool) -> NonZeroUsize {
+    NonZeroUsize::new(if value { 1 } else { 2 }).unwrap()
+}
+
+fn from_usize(value: NonZeroUsize) -> bool {
+    value.get() == 1
+}
+
+#[test]
+fn racing_initializers_agree_on_one_value() {
+    use std::sync::atomic::Ordering::Relaxed;
+    use std::sync::Barrier;
+    use std::thread;
+
+    // counts the boxes that are still around
+    struct Tracked<'a>(usize, &'a AtomicUsize);
+
+    impl Drop for Tracked<'_> {
+        fn drop(&mut self) {
+            self.1.fetch_sub(1, Relaxed);
+        }
+    }
+
+    let alive = AtomicUsize::new(0);
+    let boxed = OnceBox::new();
+    let number = OnceNonZeroUsize::new();
+    let flag = OnceBool::new();
+    let barrier = Barrier::new(8);
+
+    thread::scope(|s| {
+        let threads = Vec::from_iter((1..=8).map(|i| {
+            let (alive, boxed, number, flag, barrier) = (&alive, &boxed, &number, &flag, &barrier);
+            s.spawn(move || {
+                // all threads find the cells empty at about the same time
+                barrier.wait();
+
+                let boxed = boxed.get_or_init(|| {
+                    alive.fetch_add(1, Relaxed);
+                    Box::new(Tracked(i, alive))
+                });
+                let number = number.get_or_init(|| NonZeroUsize::new(i).unwrap());
+                let flag = flag.get_or_init(|| i % 2 == 0);
+                (boxed.0, number, flag)
+            })
+        }));
+
+        let results = Vec::from_iter(threads.into_iter().map(|t| t.join().unwrap()));
+        assert!(results.iter().all(|&result| result == results[0]));
+    });
+
+    // the boxes of the threads that lost the race are dropped already
+    assert_eq!(alive.load(Relaxed), 1);
+    alive.fetch_add(1, Relaxed);
+    assert!(boxed.set(Box::new(Tracked(0, &alive))).is_err());
+    assert_eq!(
+        number.set(NonZeroUsize::new(100).unwrap()),
+        Err(number.get().unwrap())
+    );
+    assert_eq!(flag.set(true), Err(flag.get().unwrap()));
+
+    drop(boxed);
+    assert_eq!(alive.load(Relaxed), 0);
+}
@@ -0,0 +1,177 @@
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering::{Acquire, Release};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::deadline;
+use crate::futex::{wait, wait_timeout, wake_one};
+
+const EMPTY: u32 = 0;
+const NOTIFIED: u32 = 1;
+// EMPTY - 1, so parking is a single fetch_sub
+const PARKED: u32 = u32::MAX;
+
+/// Like `thread::park`, but only ever released by its own [`Unparker`]s, not
+/// by whoever else unparks the thread, and never spuriously.
+///
+/// Unparking gives the parker a single token. Parking consumes it, or waits
+/// for it if there is none, so an unpark before the park isn't lost.
+pub struct Parker {
+    inner: Arc<Inner>,
+    // only the thread owning the Parker parks on it
+    _no_sync: PhantomData<Cell<()>>,
+}
+
+/// Releases its [`Parker`], can be cloned and sent to other threads
+#[derive(Clone)]
+pub struct Unparker {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    state: AtomicU32,
+}
+
+impl Parker {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                state: AtomicU32::new(EMPTY),
+            }),
+            _no_sync: PhantomData,
+        }
+    }
+
+    pub fn unparker(&self) -> Unparker {
+        Unparker {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+
+    /// Blocks until the token is available and consumes it
+    pub fn park(&self) {
+        let state = &self.inner.state;
+
+        // NOTIFIED -> EMPTY, or EMPTY -> PARKED
+        if state.fetch_sub(1, Acquire) == NOTIFIED {
+            return;
+        }
+
+        loop {
+            wait(state, PARKED);
+            // the futex might wake up spuriously, only the token counts
+            if state
+                .compare_exchange(NOTIFIED, EMPTY, Acquire, Acquire)
+                .is_ok()
+            {
+                return;
+            }
+        }
+    }
+
+    /// Like [`park`](Self::park), but gives up after `timeout`. Returns
+    /// true if the token was consumed.
+    pub fn park_timeout(&self, timeout: Duration) -> bool {
+        match deadline::after(timeout) {
+            Some(deadline) => self.park_deadline(deadline),
+            None => {
+                self.park();
+                true
+            }
+        }
+    }
+
+    /// Like [`park`](Self::park), but gives up at `deadline`. Returns true
+    /// if the token was consumed.
+    pub fn park_deadline(&self, deadline: Instant) -> bool {
+        let state = &self.inner.state;
+
+        if state.fetch_sub(1, Acquire) == NOTIFIED {
+            return true;
+        }
+
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                // an unpark might have happened right before
+                return state.swap(EMPTY, Acquire) == NOTIFIED;
+            }
+
+            wait_timeout(state, PARKED, deadline - now);
+            if state
+                .compare_exchange(NOTIFIED, EMPTY, Acquire, Acquire)
+                .is_ok()
+            {
+                return true;
+            }
+        }
+    }
+}
+
+impl Default for Parker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Unparker {
+    /// Makes the token available, waking up the parker if it's parked.
+    /// Unparking again before it's consumed does nothing.
+    pub fn unpark(&self) {
+        if self.inner.state.swap(NOTIFIED, Release) == PARKED {
+            wake_one(&self.inner.state);
+        }
+    }
+}
+
+#[test]
+fn the_token_is_kept_until_parking() {
+    let parker = Parker::new();
+    let unparker = parker.unparker();
+
+    // only a single token, however often it's unparked
+    unparker.unpark();
+    unparker.unpark();
+    parker.park();
+    assert!(!parker.park_timeout(Duration::from_millis(10)));
+
+    unparker.unpark();
+    assert!(parker.park_deadline(Instant::now()));
+
+    let start = Instant::now();
+    assert!(!parker.park_timeout(Duration::from_millis(20)));
+    assert!(start.elapsed() >= Duration::from_millis(20));
+}
+
+#[test]
+fn only_its_own_unparker_releases_it() {
+    use std::sync::atomic::AtomicBool;
+    use std::sync::atomic::Ordering::Relaxed;
+    use std::thread;
+
+    let released = AtomicBool::new(false);
+    let (sender, receiver) = std::sync::mpsc::channel();
+
+    thread::scope(|s| {
+        let t = s.spawn(|| {
+            let parker = Parker::new();
+            sender.send(parker.unparker()).unwrap();
+            parker.park();
+            released.store(true, Relaxed);
+        });
+        let unparker = receiver.recv().unwrap();
+
+        // unlike thread::park, unparking the thread doesn't release it
+        for _ in 0..10 {
+            t.thread().unpark();
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert!(!released.load(Relaxed));
+
+        unparker.unpark();
+        t.join().unwrap();
+        assert!(released.load(Relaxed));
+    });
+}
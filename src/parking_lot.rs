@@ -0,0 +1,276 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::{Acquire, Release};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+use crate::deadline;
+
+// Threads waiting on different addresses might share a bucket, so every
+// waiter remembers its address
+const NUM_BUCKETS: usize = 256;
+
+static BUCKETS: [Mutex<Vec<Arc<Waiter>>>; NUM_BUCKETS] =
+    [const { Mutex::new(Vec::new()) }; NUM_BUCKETS];
+
+struct Waiter {
+    addr: usize,
+    token: usize,
+    thread: Thread,
+    // only set by an unpark, with the bucket locked
+    unparked: AtomicBool,
+}
+
+/// What [`park`] returned for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParkResult {
+    /// Woken up by one of the unpark functions
+    Unparked,
+    /// `validate` returned false, so it didn't park at all
+    Invalid,
+    TimedOut,
+}
+
+/// What [`unpark_filter`] does with a waiter
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterOp {
+    Unpark,
+    /// Leaves the waiter parked and goes on with the next one
+    Skip,
+    /// Leaves this and all following waiters parked
+    Stop,
+}
+
+fn bucket(addr: usize) -> MutexGuard<'static, Vec<Arc<Waiter>>> {
+    // Fibonacci hashing, so neighbouring addresses end up in different buckets
+    let hash = (addr as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    let index = (hash >> (64 - NUM_BUCKETS.trailing_zeros())) as usize;
+
+    // a panicking validate leaves the queue as it was
+    BUCKETS[index]
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Parks the current thread in the queue of `addr`, until it's unparked or
+/// `timeout` passed. Unlike `atomic_wait::wait`, the address can be the one
+/// of any value, like an `AtomicU8` or `AtomicU64`, and every waiter can
+/// leave a `token` for [`unpark_filter`] to decide on.
+///
+/// `validate` is called with the queue locked, so no unpark for `addr` can
+/// happen in between, and the thread only parks if it returns true. It's
+/// the place to check that the value still is what's being waited on. It
+/// mustn't park or unpark itself.
+pub fn park(
+    addr: usize,
+    validate: impl FnOnce() -> bool,
+    token: usize,
+    timeout: Option<Duration>,
+) -> ParkResult {
+    let deadline = timeout.and_then(deadline::after);
+    let waiter = Arc::new(Waiter {
+        addr,
+        token,
+        thread: thread::current(),
+        unparked: AtomicBool::new(false),
+    });
+
+    let mut queue = bucket(addr);
+    if !validate() {
+        return ParkResult::Invalid;
+    }
+    queue.push(Arc::clone(&waiter));
+    drop(queue);
+
+    // thread::park might return because of anyone else unparking the
+    // thread, only the flag counts
+    while !waiter.unparked.load(Acquire) {
+        let Some(deadline) = deadline else {
+            thread::park();
+            continue;
+        };
+
+        let now = Instant::now();
+        if now < deadline {
+            thread::park_timeout(deadline - now);
+            continue;
+        }
+
+        let mut queue = bucket(addr);
+        if let Some(index) = queue.iter().position(|w| Arc::ptr_eq(w, &waiter)) {
+            queue.remove(index);
+            return ParkResult::TimedOut;
+        }
+
+        // unparked right before timing out, the flag is set while the
+        // waiter is removed from the queue
+        break;
+    }
+
+    ParkResult::Unparked
+}
+
+/// Goes through the threads parked on `addr` in the order they parked,
+/// unparking the ones `filter` picks by their token. Returns the number of
+/// threads unparked.
+pub fn unpark_filter(addr: usize, mut filter: impl FnMut(usize) -> FilterOp) -> usize {
+    let mut queue = bucket(addr);
+    let mut unparked = Vec::new();
+
+    let mut index = 0;
+    while index < queue.len() {
+        if queue[index].addr != addr {
+            index += 1;
+            continue;
+        }
+
+        match filter(queue[index].token) {
+            FilterOp::Unpark => {
+                let waiter = queue.remove(index);
+                waiter.unparked.store(true, Release);
+                unparked.push(waiter);
+            }
+            FilterOp::Skip => index += 1,
+            FilterOp::Stop => break,
+        }
+    }
+    drop(queue);
+
+    // without holding the lock, the threads can get going right away
+    for waiter in &unparked {
+        waiter.thread.unpark();
+    }
+    unparked.len()
+}
+
+/// Unparks the thread that's been parked on `addr` the longest. Returns
+/// false if there was none.
+pub fn unpark_one(addr: usize) -> bool {
+    let mut found = false;
+
+    unpark_filter(addr, |_| {
+        if found {
+            FilterOp::Stop
+        } else {
+            found = true;
+            FilterOp::Unpark
+        }
+    }) == 1
+}
+
+/// Unparks all threads parked on `addr`, returning how many there were
+pub fn unpark_all(addr: usize) -> usize {
+    unpark_filter(addr, |_| FilterOp::Unpark)
+}
+
+#[test]
+fn a_lock_on_a_single_byte() {
+    use std::cell::UnsafeCell;
+    use std::sync::atomic::AtomicU8;
+    use std::sync::atomic::Ordering::Relaxed;
+
+    // 0: unlocked, 1: locked, 2: locked with waiters, like 19-mutex.rs
+    struct ByteLock {
+        state: AtomicU8,
+        value: UnsafeCell<u64>,
+    }
+
+    unsafe impl Sync for ByteLock {}
+
+    impl ByteLock {
+        fn addr(&self) -> usize {
+            &self.state as *const AtomicU8 as usize
+        }
+
+        fn increment(&self) {
+            if self.state.compare_exchange(0, 1, Acquire, Relaxed).is_err() {
+                while self.state.swap(2, Acquire) != 0 {
+                    park(self.addr(), || self.state.load(Relaxed) == 2, 0, None);
+                }
+            }
+
+            unsafe { *self.value.get() += 1 };
+
+            if self.state.swap(0, Release) == 2 {
+                unpark_one(self.addr());
+            }
+        }
+    }
+
+    let lock = ByteLock {
+        state: AtomicU8::new(0),
+        value: UnsafeCell::new(0),
+    };
+
+    thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|| {
+                for _ in 0..10_000 {
+                    lock.increment();
+                }
+            });
+        }
+    });
+
+    assert_eq!(lock.value.into_inner(), 40_000);
+}
+
+#[test]
+fn waiters_are_picked_by_their_token() {
+    use std::sync::atomic::AtomicU64;
+    use std::sync::atomic::Ordering::Relaxed;
+
+    let value = AtomicU64::new(0);
+    let addr = &value as *const AtomicU64 as usize;
+
+    thread::scope(|s| {
+        let waiters = Vec::from_iter((0..4).map(|token| {
+            let value = &value;
+            s.spawn(move || park(addr, || value.load(Relaxed) == 0, token, None))
+        }));
+
+        // wait for all of them to be parked
+        let mut parked = 0;
+        while parked < 4 {
+            parked = 0;
+            unpark_filter(addr, |_| {
+                parked += 1;
+                FilterOp::Skip
+            });
+            thread::yield_now();
+        }
+
+        // even tokens first, then the rest
+        assert_eq!(
+            unpark_filter(addr, |token| if token % 2 == 0 {
+                FilterOp::Unpark
+            } else {
+                FilterOp::Skip
+            }),
+            2
+        );
+        assert!(unpark_one(addr));
+        assert_eq!(unpark_all(addr), 1);
+        assert!(!unpark_one(addr));
+
+        for waiter in waiters {
+            assert_eq!(waiter.join().unwrap(), ParkResult::Unparked);
+        }
+    });
+}
+
+#[test]
+fn park_checks_the_value_and_times_out() {
+    let value = 1u16;
+    let addr = &value as *const u16 as usize;
+
+    assert_eq!(park(addr, || value == 0, 0, None), ParkResult::Invalid);
+
+    let start = Instant::now();
+    let result = park(addr, || value == 1, 0, Some(Duration::from_millis(20)));
+    assert_eq!(result, ParkResult::TimedOut);
+    assert!(start.elapsed() >= Duration::from_millis(20));
+
+    // the timed out waiter isn't in the queue anymore
+    assert!(!unpark_one(addr));
+}
@@ -0,0 +1,182 @@
+use std::io::{self, stdout, IsTerminal, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::ProgressTracker;
+
+const BAR_WIDTH: usize = 30;
+
+/// One progress bar per worker thread plus one for all of them together.
+/// Workers only ever write to the recorder of their [`ProgressTracker`], while
+/// a single reporter thread does all the drawing.
+pub struct MultiProgress {
+    bars: Vec<(String, ProgressTracker)>,
+    started: Instant,
+}
+
+impl MultiProgress {
+    /// A bar for every label, with the number of items it has to process
+    pub fn new<S: Into<String>>(bars: impl IntoIterator<Item = (S, usize)>) -> Self {
+        Self {
+            bars: Vec::from_iter(
+                bars.into_iter()
+                    .map(|(label, total)| (label.into(), ProgressTracker::new(total))),
+            ),
+            started: Instant::now(),
+        }
+    }
+
+    /// The tracker to record the items of bar `index` with
+    pub fn bar(&self, index: usize) -> &ProgressTracker {
+        &self.bars[index].1
+    }
+
+    fn is_finished(&self) -> bool {
+        self.bars
+            .iter()
+            .all(|(_, tracker)| tracker.snapshot().is_finished())
+    }
+
+    /// Draws the bars to stdout whenever an item is done, but at least every
+    /// `interval`, until all bars are full. If stdout isn't a terminal, a
+    /// line is printed instead whenever the progress changed. Stops with the
+    /// error if writing to stdout fails, e.g. because it was piped into a
+    /// program that exited already.
+    pub fn report(&self, interval: Duration) -> io::Result<()> {
+        for (_, tracker) in &self.bars {
+            assert!(
+                tracker.reporter.set(thread::current()).is_ok(),
+                "the tracker has a reporter already"
+            );
+        }
+
+        let ansi = stdout().is_terminal();
+        let mut lines_drawn = 0;
+        let mut last_line = String::new();
+
+        loop {
+            let finished = self.is_finished();
+
+            let mut out = stdout().lock();
+            if ansi {
+                lines_drawn = self.redraw(&mut out, lines_drawn)?;
+            } else {
+                let line = self.summary_line();
+                if line != last_line {
+                    writeln!(out, "{line}")?;
+                    last_line = line;
+                }
+            }
+            out.flush()?;
+            drop(out);
+
+            if finished {
+                return Ok(());
+            }
+            thread::park_timeout(interval);
+        }
+    }
+
+    // Moves the cursor back up over the lines drawn last time and draws all
+    // bars again, returning the number of lines drawn
+    fn redraw(&self, out: &mut impl Write, lines_drawn: usize) -> io::Result<usize> {
+        if lines_drawn > 0 {
+            write!(out, "\x1b[{lines_drawn}A")?;
+        }
+
+        for (label, tracker) in &self.bars {
+            let progress = tracker.snapshot();
+            let line = bar_line(label, progress.done, progress.total, None);
+            write!(out, "\r\x1b[2K{line}\n")?;
+        }
+
+        let (done, total) = self.totals();
+        let line = bar_line("total", done, total, self.eta(done, total));
+        write!(out, "\r\x1b[2K{line}\n")?;
+
+        Ok(self.bars.len() + 1)
+    }
+
+    // A single line for output that isn't a terminal
+    fn summary_line(&self) -> String {
+        let (done, total) = self.totals();
+        let mut line = format!("total {done}/{total}");
+
+        for (label, tracker) in &self.bars {
+            let progress = tracker.snapshot();
+            line += &format!(" | {label} {}/{}", progress.done, progress.total);
+        }
+
+        line
+    }
+
+    fn totals(&self) -> (usize, usize) {
+        self.bars
+            .iter()
+            .map(|(_, tracker)| tracker.snapshot())
+            .fold((0, 0), |(done, total), progress| {
+                (done + progress.done, total + progress.total)
+            })
+    }
+
+    fn eta(&self, done: usize, total: usize) -> Option<Duration> {
+        let remaining = total.saturating_sub(done);
+        (done > 0).then(|| {
+            self.started
+                .elapsed()
+                .mul_f64(remaining as f64 / done as f64)
+        })
+    }
+}
+
+fn bar_line(label: &str, done: usize, total: usize, eta: Option<Duration>) -> String {
+    let filled = (done * BAR_WIDTH).checked_div(total).unwrap_or(BAR_WIDTH);
+    let filled = filled.min(BAR_WIDTH);
+
+    let mut line = format!(
+        "{label:<10} [{}{}] {done}/{total}",
+        "#".repeat(filled),
+        ".".repeat(BAR_WIDTH - filled)
+    );
+    if let Some(eta) = eta {
+        line += &format!(", {eta:.1?} left");
+    }
+
+    line
+}
+
+#[test]
+fn bars_are_redrawn_in_place() {
+    let progress = MultiProgress::new([("worker-0", 2), ("worker-1", 4)]);
+    progress.bar(0).recorder().record(Duration::from_millis(1));
+    progress.bar(1).recorder().record(Duration::from_millis(1));
+
+    let mut out = Vec::new();
+    assert_eq!(progress.redraw(&mut out, 0).unwrap(), 3);
+    let first = String::from_utf8(out).unwrap();
+
+    let lines = Vec::from_iter(first.lines());
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].ends_with(&format!("[{}{}] 1/2", "#".repeat(15), ".".repeat(15))));
+    assert!(lines[1].contains("worker-1"));
+    assert!(lines[2].contains("total"));
+    assert!(lines[2].contains("2/6"));
+    assert!(!first.contains("\x1b[3A"));
+
+    // the second time, the cursor moves up over the previous bars first
+    let mut out = Vec::new();
+    progress.redraw(&mut out, 3).unwrap();
+    assert!(String::from_utf8(out).unwrap().starts_with("\x1b[3A"));
+}
+
+#[test]
+fn summary_line_without_a_terminal() {
+    let progress = MultiProgress::new([("a", 1), ("b", 1)]);
+    progress.bar(1).recorder().record(Duration::from_millis(1));
+
+    assert_eq!(progress.summary_line(), "total 1/2 | a 0/1 | b 1/1");
+    assert!(!progress.is_finished());
+
+    progress.bar(0).recorder().record(Duration::from_millis(1));
+    assert!(progress.is_finished());
+}
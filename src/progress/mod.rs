@@ -0,0 +1,232 @@
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle, Thread};
+use std::time::{Duration, Instant};
+
+use crate::histogram::AtomicHistogram;
+use crate::seqlock::{SeqLock, SeqLockWriter};
+
+mod bars;
+
+pub use bars::MultiProgress;
+
+/// The counters from the progress reporting examples (09 to 11): any number
+/// of threads record processed items, each with its own
+/// [`ProgressRecorder`], and a reporter thread renders the progress whenever
+/// it's woken up by a new item or the interval passed.
+pub struct ProgressTracker {
+    total: usize,
+    started: Instant,
+    // one per recorder, so every worker is the single writer of its own,
+    // only locked to add a recorder or to take a snapshot
+    totals: Mutex<Vec<Arc<SeqLock<Totals>>>>,
+    times: AtomicHistogram,
+    // the thread that's unparked on progress, once there is one
+    reporter: OnceLock<Thread>,
+}
+
+// Updated together, so a snapshot never has the time of an item without
+// counting it as done, or the other way around
+#[derive(Clone, Copy, Default)]
+struct Totals {
+    done: usize,
+    // in microseconds
+    time: u64,
+    peak: u64,
+}
+
+/// The progress at one point in time, see [`ProgressTracker::snapshot`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ProgressSnapshot {
+    pub done: usize,
+    pub total: usize,
+    pub elapsed: Duration,
+    /// Items done per second since the tracker was created
+    pub rate: f64,
+    /// Average time a single item took, None if nothing is done yet
+    pub average: Option<Duration>,
+    /// Longest time a single item took
+    pub peak: Duration,
+    /// Time that half of the items took at most
+    pub p50: Option<Duration>,
+    pub p99: Option<Duration>,
+    pub p999: Option<Duration>,
+    /// Estimated time until all items are done, at the current rate
+    pub eta: Option<Duration>,
+}
+
+impl ProgressSnapshot {
+    pub fn is_finished(&self) -> bool {
+        self.done >= self.total
+    }
+}
+
+impl ProgressTracker {
+    pub fn new(total: usize) -> Self {
+        Self {
+            total,
+            started: Instant::now(),
+            totals: Mutex::new(Vec::new()),
+            times: AtomicHistogram::new(),
+            reporter: OnceLock::new(),
+        }
+    }
+
+    /// A new recorder, meant to be used by a single worker thread
+    pub fn recorder(&self) -> ProgressRecorder<'_> {
+        let totals = Arc::new(SeqLock::new(Totals::default()));
+        let writer = totals.writer().unwrap();
+        self.totals.lock().unwrap().push(totals);
+
+        ProgressRecorder {
+            tracker: self,
+            totals: writer,
+        }
+    }
+
+    /// The number of items done, the average and the peak always match, but
+    /// the percentiles might not include the latest items yet
+    pub fn snapshot(&self) -> ProgressSnapshot {
+        let totals = self
+            .totals
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|totals| totals.read())
+            .fold(Totals::default(), |sum, totals| Totals {
+                done: sum.done + totals.done,
+                time: sum.time + totals.time,
+                peak: sum.peak.max(totals.peak),
+            });
+        let done = totals.done;
+        let total_time = Duration::from_micros(totals.time);
+        let times = self.times.snapshot();
+        let percentile = |p| times.percentile(p).map(Duration::from_micros);
+        let elapsed = self.started.elapsed();
+
+        let rate = done as f64 / elapsed.as_secs_f64();
+        let average =
+            (done > 0).then(|| Duration::from_nanos((total_time.as_nanos() / done as u128) as u64));
+        let eta = (done > 0).then(|| {
+            let remaining = self.total.saturating_sub(done);
+            elapsed.mul_f64(remaining as f64 / done as f64)
+        });
+
+        ProgressSnapshot {
+            done,
+            total: self.total,
+            elapsed,
+            rate,
+            average,
+            peak: Duration::from_micros(totals.peak),
+            p50: percentile(50.0),
+            p99: percentile(99.0),
+            p999: percentile(99.9),
+            eta,
+        }
+    }
+
+    /// Calls `render` with the current progress every time an item is done,
+    /// but at least every `interval`, until all items are done. Only one
+    /// thread can be the reporter of a tracker.
+    pub fn report<F>(&self, interval: Duration, mut render: F)
+    where
+        F: FnMut(&ProgressSnapshot),
+    {
+        assert!(
+            self.reporter.set(thread::current()).is_ok(),
+            "the tracker has a reporter already"
+        );
+
+        loop {
+            let snapshot = self.snapshot();
+            render(&snapshot);
+            if snapshot.is_finished() {
+                break;
+            }
+
+            thread::park_timeout(interval);
+        }
+    }
+
+    /// Like [`ProgressTracker::report`], but on a new thread
+    pub fn spawn_reporter<F>(self: &Arc<Self>, interval: Duration, render: F) -> JoinHandle<()>
+    where
+        F: FnMut(&ProgressSnapshot) + Send + 'static,
+    {
+        let tracker = Arc::clone(self);
+        thread::spawn(move || tracker.report(interval, render))
+    }
+}
+
+/// Records the items done by one worker, see [`ProgressTracker::recorder`]
+pub struct ProgressRecorder<'a> {
+    tracker: &'a ProgressTracker,
+    totals: SeqLockWriter<Totals>,
+}
+
+impl ProgressRecorder<'_> {
+    /// Records one more item being done, that took `duration` to process.
+    /// Never waits for other workers, or the reporter.
+    pub fn record(&mut self, duration: Duration) {
+        let time_taken = duration.as_micros() as u64;
+
+        self.tracker.times.record(time_taken);
+
+        let mut totals = self.totals.write();
+        totals.done += 1;
+        totals.time += time_taken;
+        totals.peak = totals.peak.max(time_taken);
+        drop(totals);
+
+        if let Some(reporter) = self.tracker.reporter.get() {
+            reporter.unpark();
+        }
+    }
+}
+
+#[test]
+fn snapshot_summarizes_the_recorded_items() {
+    let tracker = ProgressTracker::new(4);
+
+    let snapshot = tracker.snapshot();
+    assert_eq!(snapshot.done, 0);
+    assert_eq!(snapshot.average, None);
+    assert_eq!(snapshot.eta, None);
+
+    tracker.recorder().record(Duration::from_millis(10));
+    let mut recorder = tracker.recorder();
+    recorder.record(Duration::from_millis(30));
+
+    let snapshot = tracker.snapshot();
+    assert_eq!(snapshot.done, 2);
+    assert_eq!(snapshot.total, 4);
+    assert_eq!(snapshot.average, Some(Duration::from_millis(20)));
+    assert_eq!(snapshot.peak, Duration::from_millis(30));
+    assert_eq!(snapshot.p99, Some(Duration::from_millis(30)));
+    // half done, so about as long as it took so far
+    assert!(snapshot.eta.unwrap() <= snapshot.elapsed);
+    assert!(snapshot.rate > 0.0);
+    assert!(!snapshot.is_finished());
+}
+
+#[test]
+fn reporter_is_woken_up_by_progress() {
+    let tracker = Arc::new(ProgressTracker::new(3));
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    // with an interval that long, only progress makes it render again
+    let reporter = tracker.spawn_reporter(Duration::from_secs(60), move |snapshot| {
+        sender.send(snapshot.done).unwrap();
+    });
+
+    assert_eq!(receiver.recv().unwrap(), 0);
+    let mut recorder = tracker.recorder();
+    for _ in 0..3 {
+        thread::sleep(Duration::from_millis(10));
+        recorder.record(Duration::from_millis(1));
+    }
+
+    // returns right away instead of after the interval
+    reporter.join().unwrap();
+    assert_eq!(receiver.try_iter().last(), Some(3));
+}
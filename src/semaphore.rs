@@ -0,0 +1,329 @@
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering::{Relaxed, SeqCst};
+use std::time::{Duration, Instant};
+
+use crate::deadline;
+use crate::futex::{wait, wait_timeout, wake_all};
+use crate::parking_lot::{self, FilterOp, ParkResult};
+
+/// Hands out a limited number of permits, e.g. to limit how many jobs of a
+/// ThreadPool are connected to a database at the same time.
+///
+/// By default, waiting threads sleep on the futex of the permits and grab
+/// them in whatever order they wake up. A [fair](Self::new_fair) semaphore
+/// queues them in the parking lot instead, and hands the permits over in
+/// the order they asked for them.
+pub struct Semaphore {
+    permits: AtomicU32,
+    // the threads sleeping on the futex, or those queued in the parking lot
+    // if it's fair
+    waiters: AtomicU32,
+    fair: bool,
+}
+
+/// Permits taken from a [`Semaphore`], which are given back when it's
+/// dropped
+#[must_use = "the permits are given back right away if they're dropped"]
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+    permits: u32,
+}
+
+impl Semaphore {
+    pub const fn new(permits: u32) -> Self {
+        Self {
+            permits: AtomicU32::new(permits),
+            waiters: AtomicU32::new(0),
+            fair: false,
+        }
+    }
+
+    /// A semaphore that hands out the permits in the order they were asked
+    /// for. A thread asking for many permits makes those after it wait, even
+    /// if they'd need fewer than there are available.
+    pub const fn new_fair(permits: u32) -> Self {
+        Self {
+            fair: true,
+            ..Self::new(permits)
+        }
+    }
+
+    pub fn available_permits(&self) -> u32 {
+        self.permits.load(Relaxed)
+    }
+
+    pub fn acquire(&self) -> SemaphorePermit<'_> {
+        self.acquire_many(1)
+    }
+
+    /// Blocks until `n` permits are available and takes all of them at once
+    pub fn acquire_many(&self, n: u32) -> SemaphorePermit<'_> {
+        assert!(self.acquire_until(n, None));
+        self.permit(n)
+    }
+
+    /// Like [`acquire`](Self::acquire), but gives up after `timeout`
+    pub fn acquire_timeout(&self, timeout: Duration) -> Option<SemaphorePermit<'_>> {
+        self.acquire_many_timeout(1, timeout)
+    }
+
+    /// Like [`acquire_many`](Self::acquire_many), but gives up after
+    /// `timeout`
+    pub fn acquire_many_timeout(&self, n: u32, timeout: Duration) -> Option<SemaphorePermit<'_>> {
+        self.acquire_until(n, deadline::after(timeout))
+            .then(|| self.permit(n))
+    }
+
+    /// Takes a permit if one is available right away. A fair semaphore
+    /// doesn't give it to anyone while others are waiting.
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        self.try_acquire_many(1)
+    }
+
+    pub fn try_acquire_many(&self, n: u32) -> Option<SemaphorePermit<'_>> {
+        if self.fair && self.waiters.load(SeqCst) > 0 {
+            return None;
+        }
+
+        self.try_take(n).then(|| self.permit(n))
+    }
+
+    /// Adds `n` permits, waking up the threads waiting for them
+    pub fn add_permits(&self, n: u32) {
+        // SeqCst, paired with the waiters registering themselves before
+        // checking the permits: either they see the new permits, or we see
+        // them waiting
+        self.permits.fetch_add(n, SeqCst);
+
+        if self.waiters.load(SeqCst) == 0 {
+            return;
+        }
+
+        if self.fair {
+            self.hand_over();
+        } else {
+            // they might be waiting for different numbers of permits
+            wake_all(&self.permits);
+        }
+    }
+
+    fn permit(&self, n: u32) -> SemaphorePermit<'_> {
+        SemaphorePermit {
+            semaphore: self,
+            permits: n,
+        }
+    }
+
+    fn try_take(&self, n: u32) -> bool {
+        self.permits
+            .fetch_update(SeqCst, SeqCst, |permits| permits.checked_sub(n))
+            .is_ok()
+    }
+
+    fn acquire_until(&self, n: u32, deadline: Option<Instant>) -> bool {
+        if self.fair {
+            return self.acquire_fair(n, deadline);
+        }
+
+        loop {
+            if self.try_take(n) {
+                return true;
+            }
+
+            self.waiters.fetch_add(1, SeqCst);
+            let permits = self.permits.load(SeqCst);
+            let timed_out = if permits >= n {
+                false
+            } else if let Some(deadline) = deadline {
+                let now = Instant::now();
+                if now < deadline {
+                    wait_timeout(&self.permits, permits, deadline - now);
+                }
+                now >= deadline
+            } else {
+                wait(&self.permits, permits);
+                false
+            };
+            self.waiters.fetch_sub(1, SeqCst);
+
+            if timed_out {
+                return false;
+            }
+        }
+    }
+
+    fn acquire_fair(&self, n: u32, deadline: Option<Instant>) -> bool {
+        if self.waiters.load(SeqCst) == 0 && self.try_take(n) {
+            return true;
+        }
+
+        let validate = || {
+            // runs with the queue locked, so nobody can be handed permits
+            // in between
+            let ahead = self.waiters.fetch_add(1, SeqCst);
+            if ahead == 0 && self.try_take(n) {
+                self.waiters.fetch_sub(1, SeqCst);
+                return false;
+            }
+            true
+        };
+        let timeout = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+
+        match parking_lot::park(self.addr(), validate, n as usize, timeout) {
+            // the permits were taken by us, or for us
+            ParkResult::Invalid | ParkResult::Unparked => true,
+            ParkResult::TimedOut => {
+                // we might have been first in line, holding up the others
+                self.waiters.fetch_sub(1, SeqCst);
+                self.hand_over();
+                false
+            }
+        }
+    }
+
+    // Takes the permits for the waiters at the front of the queue, as long
+    // as there are enough of them, and unparks them
+    fn hand_over(&self) {
+        parking_lot::unpark_filter(self.addr(), |wanted| {
+            if self.try_take(wanted as u32) {
+                self.waiters.fetch_sub(1, SeqCst);
+                FilterOp::Unpark
+            } else {
+                FilterOp::Stop
+            }
+        });
+    }
+
+    fn addr(&self) -> usize {
+        &self.permits as *const AtomicU32 as usize
+    }
+}
+
+impl SemaphorePermit<'_> {
+    pub fn num_permits(&self) -> u32 {
+        self.permits
+    }
+
+    /// Keeps the permits taken from the semaphore for good
+    pub fn forget(mut self) {
+        self.permits = 0;
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        if self.permits > 0 {
+            self.semaphore.add_permits(self.permits);
+        }
+    }
+}
+
+#[test]
+fn permits_are_taken_and_given_back() {
+    let semaphore = Semaphore::new(3);
+
+    let two = semaphore.acquire_many(2);
+    assert_eq!(two.num_permits(), 2);
+    let one = semaphore.try_acquire().unwrap();
+    assert!(semaphore.try_acquire().is_none());
+
+    let start = Instant::now();
+    assert!(semaphore
+        .acquire_timeout(Duration::from_millis(20))
+        .is_none());
+    assert!(start.elapsed() >= Duration::from_millis(20));
+
+    drop(two);
+    assert_eq!(semaphore.available_permits(), 2);
+    one.forget();
+    assert_eq!(semaphore.available_permits(), 2);
+
+    semaphore.add_permits(1);
+    assert!(semaphore.try_acquire_many(3).is_some());
+    assert_eq!(semaphore.available_permits(), 3);
+}
+
+#[test]
+fn no_more_threads_than_permits_at_once() {
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+
+    for semaphore in [Semaphore::new(3), Semaphore::new_fair(3)] {
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..100 {
+                        let _permit = semaphore.acquire();
+                        let now = running.fetch_add(1, Relaxed) + 1;
+                        max_running.fetch_max(now, Relaxed);
+                        thread::yield_now();
+                        running.fetch_sub(1, Relaxed);
+                    }
+                });
+            }
+        });
+
+        assert!(max_running.load(Relaxed) <= 3);
+        assert_eq!(semaphore.available_permits(), 3);
+    }
+}
+
+#[test]
+fn fair_semaphores_serve_in_order() {
+    use std::sync::mpsc::channel;
+    use std::thread;
+
+    let semaphore = Semaphore::new_fair(0);
+    let (sender, receiver) = channel();
+
+    thread::scope(|s| {
+        let first = s.spawn(|| {
+            let permit = semaphore.acquire_many(3);
+            sender.send("many").unwrap();
+            permit.forget();
+        });
+        thread::sleep(Duration::from_millis(50));
+
+        let second = s.spawn(|| {
+            let permit = semaphore.acquire();
+            sender.send("one").unwrap();
+            permit.forget();
+        });
+        thread::sleep(Duration::from_millis(50));
+
+        // there's a thread waiting already, so no barging in
+        semaphore.add_permits(1);
+        assert!(semaphore.try_acquire().is_none());
+
+        // not enough for the first thread, so the second one has to wait too
+        assert!(receiver.recv_timeout(Duration::from_millis(50)).is_err());
+
+        semaphore.add_permits(2);
+        first.join().unwrap();
+        assert_eq!(receiver.try_recv(), Ok("many"));
+
+        semaphore.add_permits(1);
+        second.join().unwrap();
+        assert_eq!(receiver.try_recv(), Ok("one"));
+    });
+
+    // a waiter that times out doesn't hold up the ones behind it
+    let semaphore = Semaphore::new_fair(1);
+    thread::scope(|s| {
+        let held = semaphore.acquire();
+        let waiter = s.spawn(|| {
+            semaphore
+                .acquire_many_timeout(2, Duration::from_millis(50))
+                .is_some()
+        });
+        thread::sleep(Duration::from_millis(10));
+        let other = s.spawn(|| semaphore.acquire().forget());
+
+        drop(held);
+        assert!(!waiter.join().unwrap());
+        other.join().unwrap();
+    });
+}
@@ -0,0 +1,164 @@
+use std::cell::UnsafeCell;
+use std::hint;
+use std::mem::MaybeUninit;
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::sync::atomic::{fence, AtomicBool, AtomicUsize};
+use std::sync::Arc;
+
+/// Plain data that's read a lot more often than it's written, like a few
+/// counters that belong together. There's a single writer, see
+/// [`writer`](Self::writer), so writing never waits. Readers never block the
+/// writer, or each other: they copy the value, and just try again if it was
+/// written in the meantime.
+pub struct SeqLock<T> {
+    // odd while it's being written, counts up with every write
+    seq: AtomicUsize,
+    value: UnsafeCell<T>,
+    // set while there's a SeqLockWriter
+    has_writer: AtomicBool,
+}
+
+unsafe impl<T: Copy + Send> Sync for SeqLock<T> {}
+
+/// The only one allowed to write to a [`SeqLock`], until it's dropped
+pub struct SeqLockWriter<T> {
+    lock: Arc<SeqLock<T>>,
+}
+
+/// Write access to the value of a [`SeqLock`], readers retry until it's
+/// dropped
+pub struct SeqLockWriteGuard<'a, T> {
+    lock: &'a SeqLock<T>,
+    seq: usize,
+}
+
+impl<T: Copy> SeqLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            seq: AtomicUsize::new(0),
+            value: UnsafeCell::new(value),
+            has_writer: AtomicBool::new(false),
+        }
+    }
+
+    /// A copy of the value, never one that's half written
+    pub fn read(&self) -> T {
+        loop {
+            let seq = self.seq.load(Acquire);
+            if !seq.is_multiple_of(2) {
+                hint::spin_loop();
+                continue;
+            }
+
+            // might race with a writer, so it's read as bytes that aren't
+            // necessarily a valid T, and only used once we know they are
+            let value = unsafe { ptr::read_volatile(self.value.get() as *const MaybeUninit<T>) };
+
+            // keeps the read of the value above from moving below the check
+            fence(Acquire);
+            if self.seq.load(Relaxed) == seq {
+                return unsafe { value.assume_init() };
+            }
+        }
+    }
+
+    /// The writer of this lock, or None if there is one already
+    pub fn writer(self: &Arc<Self>) -> Option<SeqLockWriter<T>> {
+        // Acquire, to see everything the previous writer wrote
+        (!self.has_writer.swap(true, Acquire)).then(|| SeqLockWriter {
+            lock: Arc::clone(self),
+        })
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: Copy> SeqLockWriter<T> {
+    /// Locks the value for writing. Only readers have to wait, by trying
+    /// again until the guard is dropped, so keep it short.
+    pub fn write(&mut self) -> SeqLockWriteGuard<'_, T> {
+        // no other thread changes seq, this is the only writer
+        let seq = self.lock.seq.load(Relaxed) + 1;
+        self.lock.seq.store(seq, Relaxed);
+        // keeps the writes to the value from moving above the odd sequence
+        // number
+        fence(Release);
+
+        SeqLockWriteGuard {
+            lock: &self.lock,
+            seq,
+        }
+    }
+
+    pub fn store(&mut self, value: T) {
+        *self.write() = value;
+    }
+}
+
+impl<T> Drop for SeqLockWriter<T> {
+    fn drop(&mut self) {
+        // Release, for the next writer to see what this one wrote
+        self.lock.has_writer.store(false, Release);
+    }
+}
+
+impl<T> Deref for SeqLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for SeqLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for SeqLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        // even again, publishing the new value
+        self.lock.seq.store(self.seq + 1, Release);
+    }
+}
+
+#[test]
+fn readers_never_see_half_written_values() {
+    use std::thread;
+
+    // all fields always change together
+    let lock = Arc::new(SeqLock::new([0u64; 8]));
+    let mut writer = lock.writer().unwrap();
+    assert!(lock.writer().is_none());
+
+    thread::scope(|s| {
+        s.spawn(move || {
+            for _ in 0..100_000 {
+                let mut value = writer.write();
+                let next = value[0] + 1;
+                *value = [next; 8];
+            }
+        });
+
+        for _ in 0..2 {
+            s.spawn(|| {
+                let mut last = 0;
+                for _ in 0..50_000 {
+                    let value = lock.read();
+                    assert!(value.iter().all(|&n| n == value[0]), "{value:?}");
+                    assert!(value[0] >= last);
+                    last = value[0];
+                }
+            });
+        }
+    });
+
+    // the writer was dropped, so there can be a new one
+    lock.writer().unwrap().store([1; 8]);
+    assert_eq!(Arc::into_inner(lock).unwrap().into_inner(), [1; 8]);
+}
@@ -0,0 +1,191 @@
+use std::io;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::sync::atomic::{AtomicBool, AtomicU32};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::cancellation::CancellationToken;
+use crate::deadline;
+use crate::futex;
+
+/// The signals that ask a process to shut down: Ctrl-C, `kill` and closing
+/// the terminal
+pub const SHUTDOWN_SIGNALS: [i32; 3] = [libc::SIGINT, libc::SIGTERM, libc::SIGHUP];
+
+// The lowest byte is the first shutdown signal received, or 0. The rest is
+// counted up to wake the threads waiting on the futex without a signal, so
+// they can't miss the wake up even if they were just about to go to sleep.
+static STATE: AtomicU32 = AtomicU32::new(0);
+const SIGNAL_MASK: u32 = 0xff;
+const WAKE_UP: u32 = 0x100;
+
+// Only atomics and syscalls in here, nothing that could lock or allocate
+extern "C" fn on_signal(signal: libc::c_int) {
+    let first = STATE
+        .fetch_update(Release, Relaxed, |state| {
+            (state & SIGNAL_MASK == 0).then_some(state | signal as u32)
+        })
+        .is_ok();
+
+    if first {
+        futex::wake_all(&STATE);
+    } else {
+        // a second signal while still shutting down kills the process the
+        // default way, once this handler returns
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+            libc::raise(signal);
+        }
+    }
+}
+
+/// Installs the handlers for [`SHUTDOWN_SIGNALS`]. Instead of terminating
+/// the process, the first of them is remembered, see [`received`] and
+/// [`wait_timeout`]. A second one terminates the process as usual.
+pub fn install() -> io::Result<()> {
+    for signal in SHUTDOWN_SIGNALS {
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            // blocking reads and writes carry on instead of failing
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+
+            if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The first shutdown signal received since [`install`], if any
+pub fn received() -> Option<i32> {
+    let signal = STATE.load(Acquire) & SIGNAL_MASK;
+    (signal != 0).then_some(signal as i32)
+}
+
+/// Blocks until a shutdown signal was received, or `timeout` passed
+pub fn wait_timeout(timeout: Duration) -> Option<i32> {
+    if let Some(signal) = received() {
+        return Some(signal);
+    }
+    let deadline = deadline::after(timeout);
+
+    loop {
+        let state = STATE.load(Acquire);
+        if state & SIGNAL_MASK != 0 {
+            return Some((state & SIGNAL_MASK) as i32);
+        }
+
+        let timeout = deadline::remaining(deadline)?;
+        futex::wait_timeout(&STATE, state, timeout);
+    }
+}
+
+/// Stops the thread started by [`cancel_on_signal`] when dropped
+#[must_use = "dropping it stops watching for signals right away"]
+pub struct SignalWatcher {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Installs the signal handlers and cancels `token` on the first shutdown
+/// signal. The token can't be cancelled from the signal handler itself, so a
+/// thread waits for the signal, until the token is cancelled or the returned
+/// [`SignalWatcher`] is dropped.
+pub fn cancel_on_signal(token: &CancellationToken) -> io::Result<SignalWatcher> {
+    install()?;
+
+    // wakes up the thread if the token is cancelled some other way. It's
+    // registered on a child only the thread holds, so it goes away with the
+    // thread instead of piling up on a token that's never cancelled.
+    let watched = token.child_token();
+    watched.on_cancel(wake_watchers);
+
+    let token = token.clone();
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread = thread::Builder::new()
+        .name("signal-watcher".into())
+        .spawn({
+            let stop = Arc::clone(&stop);
+            move || loop {
+                let state = STATE.load(Acquire);
+                if watched.is_cancelled() || stop.load(Relaxed) {
+                    return;
+                }
+                if state & SIGNAL_MASK != 0 {
+                    token.cancel();
+                    return;
+                }
+                futex::wait(&STATE, state);
+            }
+        })?;
+
+    Ok(SignalWatcher {
+        stop,
+        thread: Some(thread),
+    })
+}
+
+// Wakes up the watcher threads without a signal, to check if they should stop
+fn wake_watchers() {
+    STATE.fetch_add(WAKE_UP, Release);
+    futex::wake_all(&STATE);
+}
+
+impl Drop for SignalWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Relaxed);
+        wake_watchers();
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// The only test raising signals. It runs in a process of its own, as the
+// handlers and what they received are shared by the whole process.
+#[test]
+fn shutdown_signals_cancel_the_token() {
+    use std::process::Command;
+
+    if std::env::var_os("SIGNAL_TEST_CHILD").is_none() {
+        let output = Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "signal::shutdown_signals_cancel_the_token"])
+            .args(["--test-threads", "1"])
+            .env("SIGNAL_TEST_CHILD", "1")
+            .output()
+            .unwrap();
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{stdout}");
+        assert!(stdout.contains("1 passed"), "{stdout}");
+        return;
+    }
+
+    let token = CancellationToken::new();
+    let _watcher = cancel_on_signal(&token).unwrap();
+
+    assert_eq!(wait_timeout(Duration::from_millis(10)), None);
+    assert!(!token.is_cancelled());
+
+    // a token cancelled without a signal stops its watcher thread, and so
+    // does dropping the watcher of one that's never cancelled
+    let other = CancellationToken::new();
+    let other_watcher = cancel_on_signal(&other).unwrap();
+    other.cancel();
+    drop(other_watcher);
+    drop(cancel_on_signal(&CancellationToken::new()).unwrap());
+
+    // handled on this thread before raise returns
+    unsafe {
+        libc::raise(libc::SIGTERM);
+    }
+    assert_eq!(received(), Some(libc::SIGTERM));
+    assert_eq!(wait_timeout(Duration::from_secs(5)), Some(libc::SIGTERM));
+    assert!(token.wait_cancelled(Duration::from_secs(5)));
+}
@@ -0,0 +1,169 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+
+use super::ThreadPool;
+
+/// Which CPUs the workers of a pool may run on, see
+/// [`ThreadPoolBuilder::affinity`](super::ThreadPoolBuilder::affinity)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Affinity {
+    /// Every worker is pinned to a single CPU, spreading the workers evenly
+    /// over all CPUs the process may run on
+    OnePerCore,
+    /// All workers may run on any of these CPUs
+    Cores(Vec<usize>),
+}
+
+// The CPUs workers get pinned to, after applying the exclusions and leaving
+// out the CPUs the process isn't allowed to run on
+pub(super) struct Pinning {
+    cpus: Vec<usize>,
+    one_per_core: bool,
+}
+
+impl Pinning {
+    pub(super) fn new(affinity: Option<&Affinity>, exclude: &[usize]) -> io::Result<Option<Self>> {
+        if affinity.is_none() && exclude.is_empty() {
+            return Ok(None);
+        }
+
+        let allowed = current_thread_cpus()?;
+        let mut cpus = match affinity {
+            Some(Affinity::Cores(cores)) => cores.clone(),
+            Some(Affinity::OnePerCore) | None => allowed.clone(),
+        };
+        cpus.sort_unstable();
+        cpus.dedup();
+        cpus.retain(|cpu| allowed.contains(cpu) && !exclude.contains(cpu));
+
+        if cpus.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no CPU left to run the workers on",
+            ));
+        }
+
+        Ok(Some(Self {
+            cpus,
+            one_per_core: affinity == Some(&Affinity::OnePerCore),
+        }))
+    }
+
+    // The CPUs for a worker that's about to be spawned, given the CPUs of
+    // the workers that are already there
+    pub(super) fn cpus_for_new_worker(&self, workers: &HashMap<usize, Vec<usize>>) -> Vec<usize> {
+        if !self.one_per_core {
+            return self.cpus.clone();
+        }
+
+        // the CPU with the least workers pinned to it, the first one on a tie
+        let pinned_to = |cpu: usize| workers.values().filter(|cpus| **cpus == [cpu]).count();
+        let cpu = *self.cpus.iter().min_by_key(|&&cpu| pinned_to(cpu)).unwrap();
+
+        vec![cpu]
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub(super) fn pin_current_thread(cpus: &[usize]) -> io::Result<()> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for &cpu in cpus {
+            libc::CPU_SET(cpu, &mut set);
+        }
+
+        // 0 is the calling thread
+        if libc::sched_setaffinity(0, std::mem::size_of_val(&set), &set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+pub(super) fn current_thread_cpus() -> io::Result<Vec<usize>> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, std::mem::size_of_val(&set), &mut set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let max = libc::CPU_SETSIZE as usize;
+        Ok(Vec::from_iter(
+            (0..max).filter(|&cpu| libc::CPU_ISSET(cpu, &set)),
+        ))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(super) fn pin_current_thread(_cpus: &[usize]) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(super) fn current_thread_cpus() -> io::Result<Vec<usize>> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+impl ThreadPool {
+    /// The CPUs every running worker may run on, by worker id
+    pub fn worker_cpus(&self) -> BTreeMap<usize, Vec<usize>> {
+        let workers = self.shared.workers.lock();
+        BTreeMap::from_iter(workers.cpus.iter().map(|(&id, cpus)| (id, cpus.clone())))
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn workers_are_spread_over_the_cores() {
+    let allowed = current_thread_cpus().unwrap();
+
+    // twice as many workers as cores, so every core gets two of them
+    let pool = ThreadPool::builder()
+        .num_threads(allowed.len() * 2)
+        .affinity(Affinity::OnePerCore)
+        .build()
+        .unwrap();
+
+    let mut pinned_to = Vec::from_iter(pool.worker_cpus().into_values().map(|cpus| {
+        assert_eq!(cpus.len(), 1);
+        cpus[0]
+    }));
+    pinned_to.sort_unstable();
+
+    let expected = Vec::from_iter(allowed.iter().flat_map(|&cpu| [cpu, cpu]));
+    assert_eq!(pinned_to, expected);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn workers_run_on_the_given_cores_only() {
+    use std::sync::mpsc::channel;
+
+    let allowed = current_thread_cpus().unwrap();
+    let first = allowed[0];
+
+    let pool = ThreadPool::builder()
+        .num_threads(2)
+        .affinity(Affinity::Cores(vec![first]))
+        .build()
+        .unwrap();
+
+    for cpus in pool.worker_cpus().values() {
+        assert_eq!(*cpus, [first]);
+    }
+
+    let (sender, receiver) = channel();
+    pool.execute(move || sender.send(current_thread_cpus().unwrap()).unwrap());
+    assert_eq!(receiver.recv().unwrap(), [first]);
+
+    // excluding every core leaves nothing to run on
+    let error = ThreadPool::builder()
+        .num_threads(1)
+        .exclude_cores(allowed)
+        .build()
+        .err()
+        .unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+}
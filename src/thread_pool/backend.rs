@@ -0,0 +1,282 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::{Acquire, Release};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::{Job, Message, Priority};
+use crate::channel::Channel;
+
+/// Where the workers of a pool get their jobs from, see
+/// [`ThreadPoolBuilder::queue_backend`](super::ThreadPoolBuilder::queue_backend).
+///
+/// By default, a pool uses a queue that supports priorities and a capacity.
+/// Other backends only have to be a FIFO queue that can be closed.
+pub trait QueueBackend: Send + Sync {
+    /// Queues a job regardless of any capacity, like the ones of a
+    /// [`Scope`](super::Scope)
+    fn send(&self, job: Job);
+
+    /// Queues the job made by `make_job` if there's room for it. Backends
+    /// without priorities and capacity just send it.
+    fn send_bounded(&self, priority: Priority, make_job: &mut dyn FnMut() -> Job) -> Sent {
+        let _ = priority;
+        self.send(make_job());
+        Sent::Queued(None)
+    }
+
+    /// Moves a queued job to another priority, see
+    /// [`JobHandle::reprioritize`](super::JobHandle::reprioritize)
+    fn reprioritize(&self, id: u64, priority: Priority) -> bool {
+        let _ = (id, priority);
+        false
+    }
+
+    /// Waits for the next message, or returns None if there was none within
+    /// `timeout`. Waits forever if `timeout` is None. Once the queue is
+    /// closed, every worker gets [`Message::Terminate`] after the last job.
+    fn receive_timeout(&self, timeout: Option<Duration>) -> Option<Message>;
+
+    /// Lets the workers terminate once all queued jobs are done
+    fn close(&self);
+
+    /// Lets the workers terminate right away, returning the queued jobs
+    fn close_and_drain(&self) -> Vec<Job>;
+}
+
+/// What happened to a job passed to [`QueueBackend::send_bounded`]
+pub enum Sent {
+    /// With the id of the queued job, if it can be reprioritized
+    Queued(Option<u64>),
+    /// The oldest job had to make room, with the id of the queued one
+    ReplacedOldest(Job, Option<u64>),
+    /// The job didn't fit, so `make_job` wasn't called
+    Full,
+}
+
+/// Runs the pool on the [`Channel`], which is built on the futex based
+/// [`Mutex`](crate::mutex::Mutex)
+pub struct ChannelQueue {
+    channel: Channel<Message>,
+}
+
+impl ChannelQueue {
+    pub fn new() -> Self {
+        Self {
+            channel: Channel::new(),
+        }
+    }
+}
+
+impl Default for ChannelQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl QueueBackend for ChannelQueue {
+    fn send(&self, job: Job) {
+        self.channel.send(Message::NewJob(job, Instant::now()));
+    }
+
+    fn receive_timeout(&self, timeout: Option<Duration>) -> Option<Message> {
+        let message = match timeout {
+            None => Some(self.channel.receive()),
+            Some(timeout) => self.channel.receive_timeout(timeout),
+        };
+
+        // pass the Terminate on to the next worker
+        if let Some(Message::Terminate) = message {
+            self.channel.send(Message::Terminate);
+        }
+        message
+    }
+
+    // Terminate is sent after all queued jobs, so the workers run them first
+    fn close(&self) {
+        self.channel.send(Message::Terminate);
+    }
+
+    fn close_and_drain(&self) -> Vec<Job> {
+        let mut jobs = Vec::new();
+        while let Some(Message::NewJob(job, _)) = self.channel.receive_timeout(Duration::ZERO) {
+            jobs.push(job);
+        }
+
+        self.channel.send(Message::Terminate);
+        jobs
+    }
+}
+
+/// Runs the pool on `std::sync::mpsc`, with the workers taking turns on the
+/// receiving end, like the pool in the last chapter of the Rust book
+pub struct StdChannelQueue {
+    sender: Sender<Message>,
+    receiver: Mutex<Receiver<Message>>,
+    // Set when the queued jobs are taken out, workers stop right away then
+    draining: AtomicBool,
+}
+
+impl StdChannelQueue {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+
+        Self {
+            sender,
+            receiver: Mutex::new(receiver),
+            draining: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Default for StdChannelQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl QueueBackend for StdChannelQueue {
+    fn send(&self, job: Job) {
+        // can't fail, the receiver lives as long as the sender
+        let _ = self.sender.send(Message::NewJob(job, Instant::now()));
+    }
+
+    fn receive_timeout(&self, timeout: Option<Duration>) -> Option<Message> {
+        // only one worker waits on the receiver at a time, the others wait
+        // for the lock
+        let receiver = self.receiver.lock().unwrap();
+        if self.draining.load(Acquire) {
+            return Some(Message::Terminate);
+        }
+        let message = match timeout {
+            None => receiver.recv().ok(),
+            Some(timeout) => receiver.recv_timeout(timeout).ok(),
+        };
+
+        // pass the Terminate on to the next worker
+        if let Some(Message::Terminate) = message {
+            let _ = self.sender.send(Message::Terminate);
+        }
+        message
+    }
+
+    // Terminate is sent after all queued jobs, so the workers run them first
+    fn close(&self) {
+        let _ = self.sender.send(Message::Terminate);
+    }
+
+    fn close_and_drain(&self) -> Vec<Job> {
+        // an idle worker holds the lock while waiting, so it's woken up
+        // with a Terminate first to let go of it
+        self.draining.store(true, Release);
+        let _ = self.sender.send(Message::Terminate);
+        let receiver = self.receiver.lock().unwrap();
+
+        let mut jobs = Vec::new();
+        while let Ok(message) = receiver.try_recv() {
+            if let Message::NewJob(job, _) = message {
+                jobs.push(job);
+            }
+        }
+
+        let _ = self.sender.send(Message::Terminate);
+        jobs
+    }
+}
+
+// Builders for a pool on every backend besides the default one
+#[cfg(test)]
+fn backends() -> Vec<(&'static str, super::ThreadPoolBuilder)> {
+    use super::ThreadPool;
+
+    vec![
+        (
+            "channel",
+            ThreadPool::builder().queue_backend(ChannelQueue::new()),
+        ),
+        (
+            "std",
+            ThreadPool::builder().queue_backend(StdChannelQueue::new()),
+        ),
+        (
+            "lock-free",
+            ThreadPool::builder().queue_backend(super::LockFreeQueue::new()),
+        ),
+    ]
+}
+
+#[test]
+fn every_backend_runs_all_jobs() {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering::Relaxed;
+    use std::sync::Arc;
+    use std::thread;
+
+    for (name, builder) in backends() {
+        let pool = builder
+            .min_threads(1)
+            .max_threads(4)
+            .keep_alive(Duration::from_millis(20))
+            .build()
+            .unwrap();
+
+        let done = Arc::new(AtomicUsize::new(0));
+        for _ in 0..1000 {
+            let done = Arc::clone(&done);
+            pool.execute(move || {
+                done.fetch_add(1, Relaxed);
+            });
+        }
+        pool.wait_idle();
+        assert_eq!(done.load(Relaxed), 1000, "{name}");
+
+        // the extra workers time out waiting for jobs
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(pool.num_threads(), 1, "{name}");
+
+        // and the pool still finishes all queued jobs when shutting down
+        for _ in 0..1000 {
+            let done = Arc::clone(&done);
+            pool.execute(move || {
+                done.fetch_add(1, Relaxed);
+            });
+        }
+        pool.shutdown();
+        assert_eq!(done.load(Relaxed), 2000, "{name}");
+    }
+}
+
+#[test]
+fn every_backend_hands_back_queued_jobs() {
+    use std::sync::mpsc::channel;
+
+    for (name, builder) in backends() {
+        let pool = builder.num_threads(1).build().unwrap();
+
+        let (started, wait_started) = channel();
+        pool.execute(move || {
+            started.send(()).unwrap();
+            std::thread::sleep(Duration::from_millis(50));
+        });
+        wait_started.recv().unwrap();
+
+        for _ in 0..3 {
+            pool.execute(|| unreachable!());
+        }
+        assert_eq!(pool.shutdown_now().len(), 3, "{name}");
+    }
+}
+
+#[test]
+fn every_backend_shuts_down_now_while_idle() {
+    for (name, builder) in backends() {
+        let pool = builder.num_threads(2).build().unwrap();
+
+        // give the workers time to start waiting for jobs
+        pool.execute(|| {});
+        pool.wait_idle();
+        std::thread::sleep(Duration::from_millis(20));
+
+        assert!(pool.shutdown_now().is_empty(), "{name}");
+    }
+}
@@ -0,0 +1,305 @@
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+use super::{Affinity, PoolEvent, QueueBackend, RejectionPolicy, ThreadPool};
+
+type Hook = Box<dyn Fn(usize) + Send + Sync>;
+type EventHook = Box<dyn Fn(PoolEvent) + Send + Sync>;
+
+/// Configures a [`ThreadPool`] before starting it.
+///
+/// A pool starts with `min_threads` workers and spawns more, up to
+/// `max_threads`, whenever there are more queued jobs than idle workers.
+/// Workers above `min_threads` retire after being idle for `keep_alive`.
+pub struct ThreadPoolBuilder {
+    pub(super) thread_name_prefix: Option<String>,
+    pub(super) stack_size: Option<usize>,
+    pub(super) on_thread_start: Option<Hook>,
+    pub(super) on_thread_stop: Option<Hook>,
+    pub(super) on_event: Option<EventHook>,
+    pub(super) min_threads: usize,
+    pub(super) max_threads: usize,
+    pub(super) keep_alive: Duration,
+    pub(super) queue_capacity: Option<usize>,
+    // None if it wasn't set, which blocks
+    pub(super) rejection_policy: Option<RejectionPolicy>,
+    pub(super) priority_aging: Duration,
+    pub(super) affinity: Option<Affinity>,
+    pub(super) exclude_cores: Vec<usize>,
+    pub(super) queue_backend: Option<Box<dyn QueueBackend>>,
+}
+
+impl ThreadPoolBuilder {
+    /// A fixed size pool with one worker per available CPU
+    pub fn new() -> Self {
+        let num_threads = thread::available_parallelism().map_or(1, |n| n.get());
+
+        Self {
+            thread_name_prefix: None,
+            stack_size: None,
+            on_thread_start: None,
+            on_thread_stop: None,
+            on_event: None,
+            min_threads: num_threads,
+            max_threads: num_threads,
+            keep_alive: Duration::from_secs(60),
+            queue_capacity: None,
+            rejection_policy: None,
+            priority_aging: Duration::from_secs(1),
+            affinity: None,
+            exclude_cores: Vec::new(),
+            queue_backend: None,
+        }
+    }
+
+    /// Sets both the minimum and maximum number of workers
+    pub fn num_threads(self, num_threads: usize) -> Self {
+        self.min_threads(num_threads).max_threads(num_threads)
+    }
+
+    /// Number of workers that are started right away and never retire
+    pub fn min_threads(mut self, min_threads: usize) -> Self {
+        self.min_threads = min_threads;
+        self
+    }
+
+    pub fn max_threads(mut self, max_threads: usize) -> Self {
+        self.max_threads = max_threads;
+        self
+    }
+
+    /// How long a worker above `min_threads` waits for a job before retiring
+    pub fn keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// Maximum number of jobs waiting in the queue, unbounded by default.
+    /// Jobs spawned on a [`Scope`](super::Scope) don't count towards it.
+    pub fn queue_capacity(mut self, capacity: usize) -> Self {
+        self.queue_capacity = Some(capacity);
+        self
+    }
+
+    /// What to do with jobs that don't fit into the queue, blocks by default
+    pub fn rejection_policy(mut self, policy: RejectionPolicy) -> Self {
+        self.rejection_policy = Some(policy);
+        self
+    }
+
+    /// Runs the pool on another queue, like
+    /// [`LockFreeQueue`](super::LockFreeQueue). Only the default queue
+    /// supports a capacity, priorities and aging.
+    pub fn queue_backend(mut self, backend: impl QueueBackend + 'static) -> Self {
+        self.queue_backend = Some(Box::new(backend));
+        self
+    }
+
+    /// A queued job is treated as one [`Priority`](super::Priority) higher
+    /// for every `aging` it waits, so low priority jobs don't starve
+    pub fn priority_aging(mut self, aging: Duration) -> Self {
+        self.priority_aging = aging;
+        self
+    }
+
+    /// Pins the workers to CPUs, only supported on Linux. By default they
+    /// run wherever the OS schedules them.
+    pub fn affinity(mut self, affinity: Affinity) -> Self {
+        self.affinity = Some(affinity);
+        self
+    }
+
+    /// Keeps the workers off these CPUs, for example to leave them to
+    /// another latency-sensitive thread
+    pub fn exclude_cores(mut self, cores: impl IntoIterator<Item = usize>) -> Self {
+        self.exclude_cores.extend(cores);
+        self
+    }
+
+    /// Workers are named by their id appended to `prefix`
+    pub fn thread_name_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.thread_name_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Stack size of the workers in bytes, see `std::thread::Builder::stack_size`
+    pub fn stack_size(mut self, size: usize) -> Self {
+        self.stack_size = Some(size);
+        self
+    }
+
+    /// Called with the worker id on every worker thread right after it started
+    pub fn on_thread_start<F>(mut self, f: F) -> Self
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        self.on_thread_start = Some(Box::new(f));
+        self
+    }
+
+    /// Called with the worker id on every worker thread right before it exits
+    pub fn on_thread_stop<F>(mut self, f: F) -> Self
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        self.on_thread_stop = Some(Box::new(f));
+        self
+    }
+
+    /// Called for everything that happens in the pool, like a worker
+    /// starting a job, on the thread where it happened
+    pub fn on_event<F>(mut self, f: F) -> Self
+    where
+        F: Fn(PoolEvent) + Send + Sync + 'static,
+    {
+        self.on_event = Some(Box::new(f));
+        self
+    }
+
+    pub fn build(self) -> io::Result<ThreadPool> {
+        let invalid = if self.max_threads == 0 {
+            Some("max_threads must be at least 1")
+        } else if self.min_threads > self.max_threads {
+            Some("min_threads must not be more than max_threads")
+        } else if self.queue_capacity == Some(0) {
+            Some("queue_capacity must be at least 1")
+        } else if self.queue_capacity.is_some() && self.queue_backend.is_some() {
+            Some("queue_capacity can't be used with a queue_backend")
+        } else if self.rejection_policy.is_some() && self.queue_backend.is_some() {
+            Some("rejection_policy can't be used with a queue_backend")
+        } else if self.priority_aging.is_zero() {
+            Some("priority_aging must not be zero")
+        } else {
+            None
+        };
+        if let Some(message) = invalid {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+
+        ThreadPool::start(self)
+    }
+}
+
+impl Default for ThreadPoolBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn workers_are_named_and_hooks_are_called() {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering::Relaxed;
+    use std::sync::mpsc::channel;
+
+    static STARTED: AtomicUsize = AtomicUsize::new(0);
+    static STOPPED: AtomicUsize = AtomicUsize::new(0);
+
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(3)
+        .thread_name_prefix("test-worker-")
+        .stack_size(64 * 1024)
+        .on_thread_start(|_| {
+            STARTED.fetch_add(1, Relaxed);
+        })
+        .on_thread_stop(|_| {
+            STOPPED.fetch_add(1, Relaxed);
+        })
+        .build()
+        .unwrap();
+
+    let (sender, receiver) = channel();
+    pool.execute(move || {
+        let name = thread::current().name().map(String::from);
+        sender.send(name).unwrap();
+    });
+
+    let name = receiver.recv().unwrap().unwrap();
+    assert!(name.starts_with("test-worker-"), "{name}");
+
+    drop(pool);
+    assert_eq!(STARTED.load(Relaxed), 3);
+    assert_eq!(STOPPED.load(Relaxed), 3);
+}
+
+#[test]
+fn pool_grows_under_backlog_and_shrinks_when_idle() {
+    let pool = ThreadPoolBuilder::new()
+        .min_threads(1)
+        .max_threads(4)
+        .keep_alive(Duration::from_millis(50))
+        .build()
+        .unwrap();
+
+    assert_eq!(pool.num_threads(), 1);
+
+    for _ in 0..4 {
+        pool.execute(|| thread::sleep(Duration::from_millis(100)));
+    }
+    assert_eq!(pool.num_threads(), 4);
+
+    // all jobs done, and every extra worker waited longer than keep_alive
+    thread::sleep(Duration::from_millis(500));
+    assert_eq!(pool.num_threads(), 1);
+}
+
+#[test]
+fn invalid_configs_are_rejected() {
+    let configs = [
+        ThreadPoolBuilder::new().max_threads(0),
+        ThreadPoolBuilder::new().min_threads(2).max_threads(1),
+        ThreadPoolBuilder::new().queue_capacity(0),
+        ThreadPoolBuilder::new().priority_aging(Duration::ZERO),
+        // only the default queue has a capacity to reject jobs at
+        ThreadPoolBuilder::new()
+            .queue_capacity(1)
+            .queue_backend(super::LockFreeQueue::new()),
+        ThreadPoolBuilder::new()
+            .rejection_policy(RejectionPolicy::Reject)
+            .queue_backend(super::LockFreeQueue::new()),
+    ];
+
+    for config in configs {
+        let error = config.build().err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+}
+
+#[test]
+fn pools_without_min_threads_run_jobs_after_retiring() {
+    let pool = ThreadPoolBuilder::new()
+        .min_threads(0)
+        .max_threads(1)
+        .keep_alive(Duration::from_millis(1))
+        .build()
+        .unwrap();
+
+    // every job is sent about when the worker of the previous one retires,
+    // though it's rare to hit the moment it stops counting as idle exactly
+    for i in 0..200 {
+        pool.execute(|| {});
+        assert!(pool.wait_idle_timeout(Duration::from_secs(5)), "{i}");
+        thread::sleep(Duration::from_micros(500 + i * 5));
+    }
+}
+
+#[test]
+fn workers_can_be_kept_alive_forever() {
+    for builder in [
+        ThreadPoolBuilder::new(),
+        ThreadPoolBuilder::new().queue_backend(super::LockFreeQueue::new()),
+    ] {
+        let pool = builder
+            .min_threads(1)
+            .max_threads(2)
+            .keep_alive(Duration::MAX)
+            .build()
+            .unwrap();
+
+        pool.execute(|| thread::sleep(Duration::from_millis(20)));
+        pool.execute(|| {});
+        assert!(pool.wait_idle_timeout(Duration::from_secs(5)));
+        assert_eq!(pool.num_threads(), 2);
+    }
+}
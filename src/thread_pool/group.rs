@@ -0,0 +1,192 @@
+use std::sync::atomic::Ordering::{Acquire, Release};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{Shared, ThreadPool};
+
+use crate::deadline;
+use crate::mutex::{Condvar, Mutex};
+
+/// Counts the jobs submitted through it, so they can be waited for without
+/// waiting for everything else on the pool. Created with
+/// [`ThreadPool::job_group`].
+pub struct JobGroup<'pool> {
+    pool: &'pool ThreadPool,
+    state: Arc<GroupState>,
+}
+
+struct GroupState {
+    // Number of jobs that neither ran nor were dropped yet
+    pending: Mutex<usize>,
+    drained: Condvar,
+}
+
+// Moved into every job of a group, so the job is accounted for when it's
+// dropped, whether it ran, panicked, or was never run at all
+struct PendingJob(Arc<GroupState>);
+
+impl Drop for PendingJob {
+    fn drop(&mut self) {
+        let mut pending = self.0.pending.lock();
+        *pending -= 1;
+        if *pending == 0 {
+            self.0.drained.notify_all();
+        }
+    }
+}
+
+impl<'pool> JobGroup<'pool> {
+    /// Like [`ThreadPool::execute`], but the job counts towards this group
+    pub fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        *self.state.pending.lock() += 1;
+        let pending = PendingJob(Arc::clone(&self.state));
+
+        self.pool.execute(move || {
+            let _pending = pending;
+            f();
+        });
+    }
+
+    /// Number of jobs of this group that didn't finish yet
+    pub fn pending(&self) -> usize {
+        *self.state.pending.lock()
+    }
+
+    /// Blocks until all jobs of this group finished
+    pub fn wait(&self) {
+        let mut pending = self.state.pending.lock();
+        while *pending > 0 {
+            pending = self.state.drained.wait(pending);
+        }
+    }
+
+    /// Like [`JobGroup::wait`], but gives up after `timeout`. Returns true if
+    /// all jobs finished.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let deadline = deadline::after(timeout);
+        let mut pending = self.state.pending.lock();
+
+        while *pending > 0 {
+            let Some(timeout) = deadline::remaining(deadline) else {
+                return false;
+            };
+
+            pending = self.state.drained.wait_timeout(pending, timeout).0;
+        }
+
+        true
+    }
+}
+
+impl Shared {
+    // Called for every job that was queued, once it ran or was dropped
+    pub(super) fn jobs_done(&self, n: usize) {
+        if self.unfinished.fetch_sub(n, Release) == n {
+            // take the lock, so a thread in wait_idle either sees the new
+            // count or is already waiting for the notification
+            drop(self.idle_lock.lock());
+            self.became_idle.notify_all();
+        }
+    }
+}
+
+impl ThreadPool {
+    /// Starts a new, empty group of jobs
+    pub fn job_group(&self) -> JobGroup<'_> {
+        JobGroup {
+            pool: self,
+            state: Arc::new(GroupState {
+                pending: Mutex::new(0),
+                drained: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Blocks until no jobs are queued or running anymore. Jobs scheduled
+    /// for later only count once they're due. Never call this from a job of
+    /// the same pool, as it would wait for itself.
+    pub fn wait_idle(&self) {
+        let mut lock = self.shared.idle_lock.lock();
+        while self.shared.unfinished.load(Acquire) > 0 {
+            lock = self.shared.became_idle.wait(lock);
+        }
+    }
+
+    /// Like [`ThreadPool::wait_idle`], but gives up after `timeout`. Returns
+    /// true if the pool became idle.
+    pub fn wait_idle_timeout(&self, timeout: Duration) -> bool {
+        if self.shared.unfinished.load(Acquire) == 0 {
+            return true;
+        }
+
+        let deadline = deadline::after(timeout);
+        let mut lock = self.shared.idle_lock.lock();
+
+        while self.shared.unfinished.load(Acquire) > 0 {
+            let Some(timeout) = deadline::remaining(deadline) else {
+                return false;
+            };
+
+            lock = self.shared.became_idle.wait_timeout(lock, timeout).0;
+        }
+
+        true
+    }
+}
+
+#[test]
+fn wait_idle_waits_for_all_jobs() {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering::Relaxed;
+    use std::thread;
+
+    let pool = ThreadPool::new(4);
+    let done = Arc::new(AtomicUsize::new(0));
+
+    for _ in 0..20 {
+        let done = Arc::clone(&done);
+        pool.execute(move || {
+            thread::sleep(Duration::from_millis(5));
+            done.fetch_add(1, Relaxed);
+        });
+    }
+
+    pool.wait_idle();
+    assert_eq!(done.load(Relaxed), 20);
+
+    // the pool is still usable afterwards
+    pool.execute(|| thread::sleep(Duration::from_millis(200)));
+    assert!(!pool.wait_idle_timeout(Duration::from_millis(10)));
+    assert!(pool.wait_idle_timeout(Duration::from_secs(5)));
+}
+
+#[test]
+fn job_groups_are_waited_for_independently() {
+    use std::sync::mpsc::channel;
+    use std::thread;
+
+    let pool = ThreadPool::new(2);
+
+    // keeps one worker busy until the end of the test
+    let (unblock, wait_unblocked) = channel::<()>();
+    let slow = pool.job_group();
+    slow.execute(move || wait_unblocked.recv().unwrap());
+
+    let fast = pool.job_group();
+    for _ in 0..10 {
+        fast.execute(|| thread::sleep(Duration::from_millis(1)));
+    }
+    fast.execute(|| panic!("panicking jobs count as finished"));
+
+    assert!(fast.wait_timeout(Duration::from_secs(5)));
+    assert_eq!(fast.pending(), 0);
+
+    assert!(!slow.wait_timeout(Duration::from_millis(10)));
+    assert_eq!(slow.pending(), 1);
+
+    unblock.send(()).unwrap();
+    slow.wait();
+}
@@ -0,0 +1,373 @@
+use std::cell::UnsafeCell;
+use std::collections::VecDeque;
+use std::mem::MaybeUninit;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release, SeqCst};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::backend::QueueBackend;
+use super::{Job, Message};
+use crate::futex::{wait, wait_timeout, wake_all, wake_one};
+
+/// Runs the pool on a lock-free ring buffer. Sending and receiving never
+/// take a lock, only workers that find the queue empty go to sleep on a
+/// futex. Jobs that don't fit into the ring buffer go to a list behind a
+/// lock instead, which the workers only look at once the ring buffer is
+/// empty, so sending never blocks.
+pub struct LockFreeQueue {
+    slots: Box<[Slot]>,
+    // the position to receive from next
+    head: AtomicUsize,
+    // the position to send to next
+    tail: AtomicUsize,
+    closed: AtomicBool,
+    // Changed on every send, and when closing, for workers to wait on
+    futex: AtomicU32,
+    // Number of workers that might be waiting on the futex
+    sleepers: AtomicUsize,
+    // Jobs sent while the ring buffer was full, and all the ones sent after
+    // them until it's empty again, so they're still received in order
+    overflow: Mutex<VecDeque<(Job, Instant)>>,
+    // Number of jobs in overflow, to not take the lock while there are none
+    overflowed: AtomicUsize,
+}
+
+// Every slot knows which position it's ready for: a slot with a sequence of
+// `pos` can be sent to at `pos`, one with `pos + 1` holds the job for `pos`.
+// Senders and receivers claim a position with a compare exchange first, so
+// only one thread ever accesses a value.
+struct Slot {
+    sequence: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<(Job, Instant)>>,
+}
+
+unsafe impl Sync for LockFreeQueue {}
+
+impl LockFreeQueue {
+    pub fn new() -> Self {
+        Self::with_capacity(4096)
+    }
+
+    /// A queue that holds up to `capacity` jobs, rounded up to a power of two
+    pub fn with_capacity(capacity: usize) -> Self {
+        let capacity = capacity.max(2).next_power_of_two();
+
+        Self {
+            slots: Box::from_iter((0..capacity).map(|pos| Slot {
+                sequence: AtomicUsize::new(pos),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            })),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+            futex: AtomicU32::new(0),
+            sleepers: AtomicUsize::new(0),
+            overflow: Mutex::new(VecDeque::new()),
+            overflowed: AtomicUsize::new(0),
+        }
+    }
+
+    fn slot(&self, pos: usize) -> &Slot {
+        &self.slots[pos & (self.slots.len() - 1)]
+    }
+
+    fn push(&self, value: (Job, Instant)) -> Result<(), (Job, Instant)> {
+        let mut pos = self.tail.load(Relaxed);
+
+        loop {
+            let slot = self.slot(pos);
+            let sequence = slot.sequence.load(Acquire);
+
+            if sequence == pos {
+                match self
+                    .tail
+                    .compare_exchange_weak(pos, pos + 1, Relaxed, Relaxed)
+                {
+                    Ok(_) => {
+                        unsafe { (*slot.value.get()).write(value) };
+                        slot.sequence.store(pos + 1, Release);
+                        return Ok(());
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if (sequence as isize).wrapping_sub(pos as isize) < 0 {
+                // the slot still holds the job from one round ago
+                return Err(value);
+            } else {
+                // another sender claimed this position already
+                pos = self.tail.load(Relaxed);
+            }
+        }
+    }
+
+    fn pop(&self) -> Option<(Job, Instant)> {
+        let mut pos = self.head.load(Relaxed);
+
+        loop {
+            let slot = self.slot(pos);
+            let sequence = slot.sequence.load(Acquire);
+
+            if sequence == pos + 1 {
+                match self
+                    .head
+                    .compare_exchange_weak(pos, pos + 1, Relaxed, Relaxed)
+                {
+                    Ok(_) => {
+                        let value = unsafe { (*slot.value.get()).assume_init_read() };
+                        // ready to be sent to again, one round later
+                        slot.sequence.store(pos + self.slots.len(), Release);
+                        return Some(value);
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if (sequence as isize).wrapping_sub(pos as isize + 1) < 0 {
+                // nothing was sent to this position yet
+                return None;
+            } else {
+                // another receiver claimed this position already
+                pos = self.head.load(Relaxed);
+            }
+        }
+    }
+
+    fn wake(&self, all: bool) {
+        self.futex.fetch_add(1, SeqCst);
+        if self.sleepers.load(SeqCst) > 0 {
+            if all {
+                wake_all(&self.futex);
+            } else {
+                wake_one(&self.futex);
+            }
+        }
+    }
+
+    // The next job from the ring buffer, or the overflow once it's empty
+    fn take(&self) -> Option<(Job, Instant)> {
+        if let Some(value) = self.pop() {
+            return Some(value);
+        }
+        if self.overflowed.load(SeqCst) == 0 {
+            return None;
+        }
+
+        let mut overflow = self.overflow.lock().unwrap();
+        let value = overflow.pop_front()?;
+        self.overflowed.fetch_sub(1, SeqCst);
+        Some(value)
+    }
+}
+
+impl Default for LockFreeQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl QueueBackend for LockFreeQueue {
+    fn send(&self, job: Job) {
+        let mut value = (job, Instant::now());
+        if self.overflowed.load(SeqCst) == 0 {
+            match self.push(value) {
+                Ok(()) => {
+                    self.wake(false);
+                    return;
+                }
+                Err(v) => value = v,
+            }
+        }
+
+        // counted before waking up a worker, so it doesn't miss the job
+        let mut overflow = self.overflow.lock().unwrap();
+        overflow.push_back(value);
+        self.overflowed.fetch_add(1, SeqCst);
+        drop(overflow);
+
+        self.wake(false);
+    }
+
+    fn receive_timeout(&self, timeout: Option<Duration>) -> Option<Message> {
+        // too far away to be reached, e.g. for a keep_alive of Duration::MAX
+        let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+
+        loop {
+            // read before looking at the queue, so that a send after looking
+            // changes it and the wait below returns right away
+            let futex = self.futex.load(SeqCst);
+
+            if let Some((job, queued_at)) = self.take() {
+                return Some(Message::NewJob(job, queued_at));
+            }
+            if self.closed.load(Acquire) {
+                // jobs sent right before closing might not have been seen
+                // by the take above
+                return match self.take() {
+                    Some((job, queued_at)) => Some(Message::NewJob(job, queued_at)),
+                    None => Some(Message::Terminate),
+                };
+            }
+
+            self.sleepers.fetch_add(1, SeqCst);
+            match deadline {
+                None => wait(&self.futex, futex),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        self.sleepers.fetch_sub(1, SeqCst);
+                        return None;
+                    }
+
+                    wait_timeout(&self.futex, futex, deadline - now);
+                }
+            }
+            self.sleepers.fetch_sub(1, SeqCst);
+        }
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Release);
+        self.wake(true);
+    }
+
+    fn close_and_drain(&self) -> Vec<Job> {
+        self.closed.store(true, Release);
+
+        let mut jobs = Vec::new();
+        while let Some((job, _)) = self.take() {
+            jobs.push(job);
+        }
+
+        self.wake(true);
+        jobs
+    }
+}
+
+impl Drop for LockFreeQueue {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+#[test]
+fn ring_buffer_keeps_order_and_capacity() {
+    let queue = LockFreeQueue::with_capacity(3);
+    let now = Instant::now();
+
+    // rounded up to 4
+    for _ in 0..4 {
+        assert!(queue.push((Box::new(|| {}), now)).is_ok());
+    }
+    assert!(queue.push((Box::new(|| {}), now)).is_err());
+
+    // wraps around a few times
+    let (sender, receiver) = std::sync::mpsc::channel();
+    for i in 0..4 {
+        drop(queue.pop().unwrap());
+        let sender = sender.clone();
+        queue
+            .push((Box::new(move || sender.send(i).unwrap()), now))
+            .ok();
+    }
+    while let Some((job, _)) = queue.pop() {
+        job();
+    }
+    assert_eq!(Vec::from_iter(receiver.try_iter()), [0, 1, 2, 3]);
+}
+
+#[test]
+fn many_senders_and_receivers() {
+    use std::sync::atomic::AtomicU64;
+    use std::thread;
+
+    static SUM: AtomicU64 = AtomicU64::new(0);
+
+    let queue = LockFreeQueue::with_capacity(64);
+
+    thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|| {
+                while let Some(Message::NewJob(job, _)) = queue.receive_timeout(None) {
+                    job();
+                }
+            });
+        }
+
+        // more jobs than fit into the ring buffer at once, some overflow
+        thread::scope(|s| {
+            for t in 0..4 {
+                let queue = &queue;
+                s.spawn(move || {
+                    for i in 0..10_000 {
+                        let n = t * 10_000 + i;
+                        queue.send(Box::new(move || {
+                            SUM.fetch_add(n, Relaxed);
+                        }));
+                    }
+                });
+            }
+        });
+
+        // closing only lets the receivers stop once the queue is empty
+        queue.close();
+    });
+
+    assert_eq!(SUM.load(Relaxed), (0..40_000).sum());
+}
+
+#[test]
+fn sending_to_a_full_queue_overflows_in_order() {
+    let queue = LockFreeQueue::with_capacity(2);
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    for i in 0..5 {
+        let sender = sender.clone();
+        queue.send(Box::new(move || sender.send(i).unwrap()));
+    }
+    assert_eq!(queue.overflowed.load(Relaxed), 3);
+
+    // a job sent once there's room again still comes after the overflow
+    let Some(Message::NewJob(job, _)) = queue.receive_timeout(Some(Duration::ZERO)) else {
+        panic!("a job should have been queued");
+    };
+    job();
+    queue.send(Box::new(move || sender.send(5).unwrap()));
+
+    while let Some(Message::NewJob(job, _)) = queue.receive_timeout(Some(Duration::ZERO)) {
+        job();
+    }
+    assert_eq!(Vec::from_iter(receiver.try_iter()), [0, 1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn scopes_can_overflow_a_small_ring_buffer() {
+    use super::ThreadPool;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::mpsc::channel;
+    use std::sync::Arc;
+
+    let pool = Arc::new(
+        ThreadPool::builder()
+            .num_threads(1)
+            .queue_backend(LockFreeQueue::with_capacity(2))
+            .build()
+            .unwrap(),
+    );
+    let (sender, receiver) = channel();
+
+    // the only worker sends more scoped jobs than fit into the ring buffer
+    let inner = Arc::clone(&pool);
+    pool.execute(move || {
+        let sum = AtomicUsize::new(0);
+        inner.scope(|s| {
+            for n in 0..10 {
+                let sum = &sum;
+                s.spawn(move || {
+                    sum.fetch_add(n, Relaxed);
+                });
+            }
+        });
+        sender.send(sum.into_inner()).unwrap();
+    });
+
+    assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(45));
+}
@@ -0,0 +1,552 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::{Relaxed, SeqCst};
+use std::sync::{Arc, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use crate::cancellation::CancellationToken;
+use crate::mutex::{Condvar, Mutex};
+
+mod affinity;
+mod backend;
+mod builder;
+mod group;
+mod lock_free;
+mod par_iter;
+mod priority;
+mod queue;
+mod scope;
+mod shutdown;
+mod stats;
+mod timer;
+
+pub use affinity::Affinity;
+pub use backend::{ChannelQueue, QueueBackend, Sent, StdChannelQueue};
+pub use builder::ThreadPoolBuilder;
+pub use group::JobGroup;
+pub use lock_free::LockFreeQueue;
+pub use par_iter::{Chunks, ChunksIter, ParallelSource};
+pub use priority::{JobHandle, Priority};
+pub use queue::{Rejected, RejectionPolicy};
+pub use scope::Scope;
+pub use shutdown::ShutdownTimedOut;
+pub use stats::{PoolEvent, PoolStats};
+pub use timer::TimerHandle;
+
+use affinity::Pinning;
+use queue::JobQueue;
+use stats::Stats;
+use timer::Timer;
+
+pub type Job = Box<dyn FnOnce() + Send + 'static>;
+
+pub enum Message {
+    /// A job together with the time it was queued at
+    NewJob(Job, Instant),
+    Terminate,
+}
+
+pub struct ThreadPool {
+    shared: Arc<Shared>,
+}
+
+// State shared between the pool and all of its workers
+struct Shared {
+    config: ThreadPoolBuilder,
+    queue: Box<dyn QueueBackend>,
+    // None if the workers aren't pinned to any CPUs
+    pinning: Option<Pinning>,
+    // Started when the first job is scheduled
+    timer: OnceLock<Timer>,
+    workers: Mutex<Workers>,
+    // Notified whenever a worker leaves its loop
+    worker_exited: Condvar,
+    // Id for the next worker that's spawned
+    next_id: AtomicUsize,
+    // Number of running workers
+    num_workers: AtomicUsize,
+    // Number of jobs sent, but not picked up by a worker yet
+    queued: AtomicUsize,
+    // Number of workers waiting for a job
+    idle: AtomicUsize,
+    // Number of jobs sent, but not finished yet
+    unfinished: AtomicUsize,
+    // Notified when unfinished drops to zero
+    idle_lock: Mutex<()>,
+    became_idle: Condvar,
+    stats: Stats,
+}
+
+struct Workers {
+    // Join handles of all workers that weren't joined yet, by worker id
+    threads: HashMap<usize, JoinHandle<()>>,
+    // Ids of all workers that didn't leave their loop yet
+    running: HashSet<usize>,
+    // CPUs every worker that didn't leave its loop yet may run on
+    cpus: HashMap<usize, Vec<usize>>,
+}
+
+impl ThreadPool {
+    pub fn new(size: usize) -> Self {
+        ThreadPoolBuilder::new().num_threads(size).build().unwrap()
+    }
+
+    pub fn builder() -> ThreadPoolBuilder {
+        ThreadPoolBuilder::new()
+    }
+
+    fn start(mut config: ThreadPoolBuilder) -> io::Result<Self> {
+        let min_threads = config.min_threads;
+        let pinning = Pinning::new(config.affinity.as_ref(), &config.exclude_cores)?;
+        let queue = config.queue_backend.take().unwrap_or_else(|| {
+            Box::new(JobQueue::new(
+                config.queue_capacity,
+                config.rejection_policy.unwrap_or(RejectionPolicy::Block),
+                config.priority_aging,
+            ))
+        });
+        let pool = Self {
+            shared: Arc::new(Shared {
+                config,
+                queue,
+                pinning,
+                timer: OnceLock::new(),
+                workers: Mutex::new(Workers {
+                    threads: HashMap::new(),
+                    running: HashSet::new(),
+                    cpus: HashMap::new(),
+                }),
+                worker_exited: Condvar::new(),
+                next_id: AtomicUsize::new(0),
+                num_workers: AtomicUsize::new(0),
+                queued: AtomicUsize::new(0),
+                idle: AtomicUsize::new(0),
+                unfinished: AtomicUsize::new(0),
+                idle_lock: Mutex::new(()),
+                became_idle: Condvar::new(),
+                stats: Stats::new(),
+            }),
+        };
+
+        // if spawning fails, dropping the pool stops the workers spawned so far
+        for _ in 0..min_threads {
+            pool.shared.num_workers.fetch_add(1, Relaxed);
+            if let Err(e) = Worker::spawn(&pool.shared) {
+                pool.shared.num_workers.fetch_sub(1, Relaxed);
+                return Err(e);
+            }
+        }
+
+        Ok(pool)
+    }
+
+    /// Number of worker threads currently running
+    pub fn num_threads(&self) -> usize {
+        self.shared.num_workers.load(Relaxed)
+    }
+
+    /// Number of worker threads the pool may grow to
+    pub fn max_threads(&self) -> usize {
+        self.shared.config.max_threads
+    }
+
+    /// Queues `f` to be run by one of the workers. Panics if the queue is
+    /// full and the pool was configured with [`RejectionPolicy::Reject`].
+    pub fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.execute_with_priority(Priority::Normal, f);
+    }
+
+    /// Queues `f` to be run by one of the workers. If the queue is full,
+    /// what happens depends on the pool's [`RejectionPolicy`].
+    pub fn try_execute<F>(&self, f: F) -> Result<(), Rejected<F>>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.try_execute_with_priority(Priority::Normal, f)
+            .map(|_| ())
+    }
+
+    /// Like [`ThreadPool::execute`], but workers pick up jobs with a higher
+    /// priority first. The returned handle can change the priority while the
+    /// job is still queued.
+    pub fn execute_with_priority<F>(&self, priority: Priority, f: F) -> JobHandle
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        match self.try_execute_with_priority(priority, f) {
+            Ok(handle) => handle,
+            Err(_) => panic!("job rejected, the queue is full"),
+        }
+    }
+
+    pub fn try_execute_with_priority<F>(
+        &self,
+        priority: Priority,
+        f: F,
+    ) -> Result<JobHandle, Rejected<F>>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.shared.unfinished.fetch_add(1, Relaxed);
+        let queued = self.shared.queued.fetch_add(1, SeqCst) + 1;
+
+        // only boxed once the queue takes it, so a rejected job can be
+        // handed back as it is
+        let mut f = Some(f);
+        let sent = self
+            .shared
+            .queue
+            .send_bounded(priority, &mut || Box::new(f.take().unwrap()));
+
+        match sent {
+            Sent::Queued(id) => {
+                self.shared.job_queued(queued);
+                Ok(JobHandle::new(&self.shared, id))
+            }
+            Sent::ReplacedOldest(oldest, id) => {
+                self.shared.queued.fetch_sub(1, Relaxed);
+                drop(oldest);
+                self.shared.jobs_done(1);
+                Ok(JobHandle::new(&self.shared, id))
+            }
+            Sent::Full => {
+                let f = f.unwrap();
+                self.shared.queued.fetch_sub(1, Relaxed);
+                self.shared.jobs_done(1);
+                match self.shared.config.rejection_policy {
+                    Some(RejectionPolicy::CallerRuns) => {
+                        f();
+                        Ok(JobHandle::new(&self.shared, None))
+                    }
+                    _ => Err(Rejected(f)),
+                }
+            }
+        }
+    }
+
+    /// Like [`ThreadPool::execute`], but the job is skipped if `token` is
+    /// cancelled before a worker gets to it. Once running, the job can check
+    /// the token it's given to stop early.
+    pub fn execute_with_token<F>(&self, token: &CancellationToken, f: F)
+    where
+        F: FnOnce(&CancellationToken) + Send + 'static,
+    {
+        let token = token.clone();
+        self.execute(move || {
+            if !token.is_cancelled() {
+                f(&token);
+            }
+        });
+    }
+
+    // Queues a job regardless of the queue's capacity
+    fn send_job(&self, job: Job) {
+        self.shared.send_job(job);
+    }
+
+    // true if more workers are waiting than there are jobs queued for them,
+    // or the pool may still grow, so work handed to the pool right now would
+    // be picked up immediately
+    fn has_idle_workers(&self) -> bool {
+        self.shared.idle.load(Relaxed) > self.shared.queued.load(Relaxed)
+            || self.num_threads() < self.max_threads()
+    }
+}
+
+impl Shared {
+    fn event(&self, event: PoolEvent) {
+        if let Some(on_event) = &self.config.on_event {
+            on_event(event);
+        }
+    }
+
+    // Queues a job regardless of the queue's capacity
+    fn send_job(self: &Arc<Self>, job: Job) {
+        self.unfinished.fetch_add(1, Relaxed);
+        let queued = self.queued.fetch_add(1, SeqCst) + 1;
+        self.queue.send(job);
+        self.job_queued(queued);
+    }
+
+    // SeqCst, together with the worker's retire(), so that either the
+    // sender sees the worker isn't idle anymore, or the worker sees the job
+    fn job_queued(self: &Arc<Self>, queued: usize) {
+        if queued > self.idle.load(SeqCst) {
+            self.grow();
+        }
+    }
+
+    // Spawns another worker, unless there are max_threads already
+    fn grow(self: &Arc<Self>) {
+        // not being able to grow is fine, the running workers will get to the job
+        if self.grow_by_one() && Worker::spawn(self).is_err() {
+            self.num_workers.fetch_sub(1, Relaxed);
+        }
+    }
+
+    // Counts one more worker, unless there are max_threads already. Returns
+    // true if it did, the caller has to start it then.
+    fn grow_by_one(&self) -> bool {
+        let max_threads = self.config.max_threads;
+        self.num_workers
+            .fetch_update(Relaxed, Relaxed, |n| (n < max_threads).then_some(n + 1))
+            .is_ok()
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.stop_timer();
+
+        // the workers finish all queued jobs before they terminate
+        self.shared.queue.close();
+
+        // the last reference to the pool might be dropped by one of its own
+        // jobs, that worker can't wait for itself, and exits on its own
+        let current = thread::current().id();
+        let threads = Vec::from_iter(self.shared.workers.lock().threads.drain());
+        for (_, thread) in threads {
+            if thread.thread().id() != current {
+                thread.join().unwrap();
+            }
+        }
+    }
+}
+
+struct Worker {
+    id: usize,
+    shared: Arc<Shared>,
+}
+
+impl Worker {
+    // The caller has to account for the new worker in num_workers
+    fn spawn(shared: &Arc<Shared>) -> io::Result<()> {
+        let id = shared.next_id.fetch_add(1, Relaxed);
+
+        let mut builder = thread::Builder::new();
+        if let Some(prefix) = &shared.config.thread_name_prefix {
+            builder = builder.name(format!("{prefix}{id}"));
+        }
+        if let Some(size) = shared.config.stack_size {
+            builder = builder.stack_size(size);
+        }
+
+        // the Worker is only created on the new thread, as dropping it takes
+        // the lock we're holding below if spawning fails
+        let worker_shared = Arc::clone(shared);
+
+        // hold the lock until the handle is stored, in case the worker
+        // retires right away and wants to remove it again
+        let mut workers = shared.workers.lock();
+        let cpus = shared
+            .pinning
+            .as_ref()
+            .map(|pinning| pinning.cpus_for_new_worker(&workers.cpus));
+
+        let thread = builder.spawn({
+            let cpus = cpus.clone();
+            move || {
+                let worker = Worker {
+                    id,
+                    shared: worker_shared,
+                };
+                worker.run(cpus)
+            }
+        })?;
+        workers.threads.insert(id, thread);
+        workers.running.insert(id);
+        // so the next worker is pinned to another CPU
+        if let Some(cpus) = cpus {
+            workers.cpus.insert(id, cpus);
+        }
+
+        Ok(())
+    }
+
+    fn run(self, cpus: Option<Vec<usize>>) {
+        let config = &self.shared.config;
+
+        if let Some(cpus) = cpus {
+            // the CPUs are checked when starting the pool, so this only fails
+            // if the process got restricted to other CPUs in the meantime
+            let _ = affinity::pin_current_thread(&cpus);
+        }
+        // record where the worker actually runs, even if pinning failed
+        if let Ok(cpus) = affinity::current_thread_cpus() {
+            self.shared.workers.lock().cpus.insert(self.id, cpus);
+        }
+
+        if let Some(on_thread_start) = &config.on_thread_start {
+            on_thread_start(self.id);
+        }
+        self.shared
+            .event(PoolEvent::WorkerStarted { worker: self.id });
+
+        // a fixed size pool never retires workers
+        let timeout = (config.min_threads < config.max_threads).then_some(config.keep_alive);
+
+        loop {
+            self.shared.idle.fetch_add(1, Relaxed);
+            let message = self.shared.queue.receive_timeout(timeout);
+            self.shared.idle.fetch_sub(1, SeqCst);
+
+            match message {
+                Some(Message::NewJob(job, queued_at)) => {
+                    self.shared.queued.fetch_sub(1, Relaxed);
+                    self.run_job(job, queued_at);
+                }
+                Some(Message::Terminate) => {
+                    self.shared
+                        .event(PoolEvent::WorkerTerminated { worker: self.id });
+                    break;
+                }
+                None => {
+                    if self.retire() {
+                        self.shared
+                            .event(PoolEvent::WorkerRetired { worker: self.id });
+                        break;
+                    }
+                }
+            }
+        }
+
+        if let Some(on_thread_stop) = &config.on_thread_stop {
+            on_thread_stop(self.id);
+        }
+    }
+
+    fn run_job(&self, job: Job, queued_at: Instant) {
+        let queue_wait = queued_at.elapsed();
+        self.shared.stats.job_started(queue_wait);
+        self.shared.event(PoolEvent::JobStarted {
+            worker: self.id,
+            queue_wait,
+        });
+
+        // a panicking job shouldn't take the worker down with it
+        let start = Instant::now();
+        let panicked = catch_unwind(AssertUnwindSafe(job)).is_err();
+        let execution_time = start.elapsed();
+
+        self.shared.stats.job_finished(execution_time, panicked);
+        self.shared.event(PoolEvent::JobFinished {
+            worker: self.id,
+            execution_time,
+            panicked,
+        });
+        self.shared.jobs_done(1);
+    }
+
+    // Removes this worker from the pool, unless that would leave the pool
+    // with less than min_threads workers
+    fn retire(&self) -> bool {
+        let min_threads = self.shared.config.min_threads;
+        let retired = self
+            .shared
+            .num_workers
+            .fetch_update(Relaxed, Relaxed, |n| (n > min_threads).then_some(n - 1))
+            .is_ok();
+        if !retired {
+            return false;
+        }
+
+        // a job sent while this worker still counted as idle didn't make the
+        // pool grow, and there might be no other worker left to take it, so
+        // this one stays after all. If counting it again fails, another
+        // worker was just spawned instead.
+        if self.shared.queued.load(SeqCst) > self.shared.idle.load(Relaxed)
+            && self.shared.grow_by_one()
+        {
+            return false;
+        }
+
+        // nobody needs to join this thread anymore
+        self.shared.workers.lock().threads.remove(&self.id);
+        true
+    }
+}
+
+impl Drop for Worker {
+    // also runs if a job panicked and took the worker down with it
+    fn drop(&mut self) {
+        let mut workers = self.shared.workers.lock();
+        workers.running.remove(&self.id);
+        workers.cpus.remove(&self.id);
+        drop(workers);
+
+        self.shared.worker_exited.notify_all();
+    }
+}
+
+#[test]
+fn cancelled_jobs_are_skipped() {
+    use std::sync::atomic::AtomicUsize;
+
+    let (pool, unblock) = queue::blocked_pool(ThreadPool::builder());
+    let token = CancellationToken::new();
+    let runs = Arc::new(AtomicUsize::new(0));
+
+    for token in [token.clone(), token.child_token()] {
+        let runs = Arc::clone(&runs);
+        pool.execute_with_token(&token, move |_| {
+            runs.fetch_add(1, Relaxed);
+        });
+    }
+
+    // both jobs are still queued behind the blocking one
+    token.cancel();
+    unblock.send(()).unwrap();
+    pool.shutdown();
+
+    assert_eq!(runs.load(Relaxed), 0);
+}
+
+#[test]
+fn running_jobs_see_the_cancellation() {
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    let pool = ThreadPool::new(1);
+    let token = CancellationToken::new();
+
+    let (started, wait_started) = channel();
+    let (result, wait_result) = channel();
+    pool.execute_with_token(&token, move |token| {
+        started.send(()).unwrap();
+        result
+            .send(token.wait_cancelled(Duration::from_secs(5)))
+            .unwrap();
+    });
+
+    wait_started.recv().unwrap();
+    token.cancel();
+    assert!(wait_result.recv().unwrap());
+}
+
+#[test]
+fn pool_can_be_dropped_by_its_own_job() {
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    let pool = Arc::new(ThreadPool::new(2));
+    let (dropped, wait_dropped) = channel();
+    let (done, wait_done) = channel();
+
+    let inner = Arc::clone(&pool);
+    pool.execute(move || {
+        wait_dropped.recv().unwrap();
+        // the last reference, so this waits for the other worker only
+        drop(inner);
+        done.send(()).unwrap();
+    });
+
+    drop(pool);
+    dropped.send(()).unwrap();
+    assert_eq!(wait_done.recv_timeout(Duration::from_secs(5)), Ok(()));
+}
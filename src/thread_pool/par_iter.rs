@@ -0,0 +1,316 @@
+use std::cmp::max;
+use std::ops::Range;
+use std::sync::Mutex;
+
+use super::{Scope, ThreadPool};
+
+/// Something that can be split into independent parts and processed in
+/// parallel on a [`ThreadPool`], like a slice or a range.
+pub trait ParallelSource: Sized + Send {
+    type Item;
+    type Iter: Iterator<Item = Self::Item>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Splits into `[0, index)` and `[index, len)`
+    fn split_at(self, index: usize) -> (Self, Self);
+
+    /// Iterates over all items sequentially, on the current thread
+    fn into_seq_iter(self) -> Self::Iter;
+}
+
+impl<'a, T: Sync> ParallelSource for &'a [T] {
+    type Item = &'a T;
+    type Iter = std::slice::Iter<'a, T>;
+
+    fn len(&self) -> usize {
+        <[T]>::len(self)
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        <[T]>::split_at(self, index)
+    }
+
+    fn into_seq_iter(self) -> Self::Iter {
+        self.iter()
+    }
+}
+
+impl<'a, T: Send> ParallelSource for &'a mut [T] {
+    type Item = &'a mut T;
+    type Iter = std::slice::IterMut<'a, T>;
+
+    fn len(&self) -> usize {
+        <[T]>::len(self)
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        self.split_at_mut(index)
+    }
+
+    fn into_seq_iter(self) -> Self::Iter {
+        self.iter_mut()
+    }
+}
+
+impl ParallelSource for Range<usize> {
+    type Item = usize;
+    type Iter = Range<usize>;
+
+    fn len(&self) -> usize {
+        ExactSizeIterator::len(self)
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let mid = self.start + index;
+        (self.start..mid, mid..self.end)
+    }
+
+    fn into_seq_iter(self) -> Self::Iter {
+        self
+    }
+}
+
+/// A [`ParallelSource`] over consecutive chunks of another source, see
+/// [`ThreadPool::par_chunks`]. The last chunk may be shorter.
+pub struct Chunks<S> {
+    source: S,
+    chunk_size: usize,
+}
+
+impl<S: ParallelSource> ParallelSource for Chunks<S> {
+    type Item = S;
+    type Iter = ChunksIter<S>;
+
+    fn len(&self) -> usize {
+        self.source.len().div_ceil(self.chunk_size)
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let index = (index * self.chunk_size).min(self.source.len());
+        let (left, right) = self.source.split_at(index);
+        (
+            Chunks {
+                source: left,
+                chunk_size: self.chunk_size,
+            },
+            Chunks {
+                source: right,
+                chunk_size: self.chunk_size,
+            },
+        )
+    }
+
+    fn into_seq_iter(self) -> Self::Iter {
+        ChunksIter {
+            source: Some(self.source),
+            chunk_size: self.chunk_size,
+        }
+    }
+}
+
+pub struct ChunksIter<S> {
+    source: Option<S>,
+    chunk_size: usize,
+}
+
+impl<S: ParallelSource> Iterator for ChunksIter<S> {
+    type Item = S;
+
+    fn next(&mut self) -> Option<S> {
+        let source = self.source.take()?;
+
+        if source.is_empty() {
+            None
+        } else if source.len() <= self.chunk_size {
+            Some(source)
+        } else {
+            let (chunk, rest) = source.split_at(self.chunk_size);
+            self.source = Some(rest);
+            Some(chunk)
+        }
+    }
+}
+
+impl ThreadPool {
+    /// Calls `f` for every item of `source`, in parallel on the pool
+    pub fn par_for_each<S, F>(&self, source: S, f: F)
+    where
+        S: ParallelSource,
+        F: Fn(S::Item) + Sync,
+    {
+        self.par_split(source, |_, part| part.into_seq_iter().for_each(&f));
+    }
+
+    /// Maps every item of `source` with `f`, in parallel on the pool. The
+    /// results are in the same order as the items.
+    pub fn par_map<S, F, R>(&self, source: S, f: F) -> Vec<R>
+    where
+        S: ParallelSource,
+        F: Fn(S::Item) -> R + Sync,
+        R: Send,
+    {
+        let len = source.len();
+        let parts = Mutex::new(Vec::new());
+
+        self.par_split(source, |offset, part| {
+            let results: Vec<R> = part.into_seq_iter().map(&f).collect();
+            parts.lock().unwrap().push((offset, results));
+        });
+
+        let mut parts = parts.into_inner().unwrap();
+        parts.sort_unstable_by_key(|(offset, _)| *offset);
+
+        let mut results = Vec::with_capacity(len);
+        for (_, part) in parts {
+            results.extend(part);
+        }
+        results
+    }
+
+    /// Maps every item of `source` with `map` and combines the results with
+    /// `op`, in parallel on the pool. `op` has to be associative and
+    /// `identity()` a neutral element for it, as every part starts with it.
+    pub fn par_reduce<S, T, ID, M, OP>(&self, source: S, identity: ID, map: M, op: OP) -> T
+    where
+        S: ParallelSource,
+        T: Send,
+        ID: Fn() -> T + Sync,
+        M: Fn(S::Item) -> T + Sync,
+        OP: Fn(T, T) -> T + Sync,
+    {
+        let parts = Mutex::new(Vec::new());
+
+        self.par_split(source, |offset, part| {
+            let result = part.into_seq_iter().map(&map).fold(identity(), &op);
+            parts.lock().unwrap().push((offset, result));
+        });
+
+        // op doesn't need to be commutative, so combine the parts in order
+        let mut parts = parts.into_inner().unwrap();
+        parts.sort_unstable_by_key(|(offset, _)| *offset);
+
+        parts
+            .into_iter()
+            .map(|(_, result)| result)
+            .fold(identity(), &op)
+    }
+
+    /// Calls `f` for consecutive chunks of `chunk_size` items of `source`, in
+    /// parallel on the pool, and returns the results in the order of the
+    /// chunks. Only the last chunk may be shorter.
+    pub fn par_chunks<S, F, R>(&self, source: S, chunk_size: usize, f: F) -> Vec<R>
+    where
+        S: ParallelSource,
+        F: Fn(S) -> R + Sync,
+        R: Send,
+    {
+        assert!(chunk_size > 0);
+
+        self.par_map(Chunks { source, chunk_size }, f)
+    }
+
+    // Calls `part` for non-overlapping parts of `source` together with their
+    // offsets, until all items have been handed out. Instead of splitting in
+    // a fixed number of parts up front, a job only splits off half of its
+    // remaining items while there are idle workers to pick them up. The
+    // calling thread helps out while waiting, so this works from within a
+    // job of the same pool too.
+    fn par_split<S, P>(&self, source: S, part: P)
+    where
+        S: ParallelSource,
+        P: Fn(usize, S) + Sync,
+    {
+        if source.is_empty() {
+            return;
+        }
+
+        // process a few items at a time in between checking for idle workers
+        let min_len = max(1, source.len() / (self.max_threads() * 16));
+
+        self.scope(|s| {
+            let part = &part;
+            s.spawn(move || split(self, s, source, 0, min_len, part));
+        });
+    }
+}
+
+fn split<'scope, S, P>(
+    pool: &'scope ThreadPool,
+    scope: &'scope Scope<'scope, '_>,
+    mut source: S,
+    mut offset: usize,
+    min_len: usize,
+    part: &'scope P,
+) where
+    S: ParallelSource + 'scope,
+    P: Fn(usize, S) + Sync,
+{
+    loop {
+        let len = source.len();
+        if len <= min_len {
+            part(offset, source);
+            return;
+        }
+
+        if pool.has_idle_workers() {
+            let mid = len / 2;
+            let (left, right) = source.split_at(mid);
+            scope.spawn(move || split(pool, scope, right, offset + mid, min_len, part));
+            source = left;
+        } else {
+            let (head, tail) = source.split_at(min_len);
+            part(offset, head);
+            source = tail;
+            offset += min_len;
+        }
+    }
+}
+
+#[test]
+fn par_helpers_match_sequential_results() {
+    let pool = ThreadPool::new(4);
+    let numbers = Vec::from_iter(0..=1000usize);
+
+    let squares = pool.par_map(numbers.as_slice(), |n| n * n);
+    assert_eq!(squares, Vec::from_iter((0..=1000).map(|n| n * n)));
+
+    let sum = pool.par_reduce(0..1001, || 0, |n| n, |a, b| a + b);
+    assert_eq!(sum, 500500);
+
+    // string concatenation is associative, but not commutative
+    let joined = pool.par_reduce(0..100, String::new, |n| n.to_string(), |a, b| a + &b);
+    assert_eq!(joined, String::from_iter((0..100).map(|n| n.to_string())));
+
+    let chunk_sums = pool.par_chunks(numbers.as_slice(), 300, |chunk| chunk.iter().sum::<usize>());
+    assert_eq!(chunk_sums, [44850, 134850, 224850, 95950]);
+
+    let mut doubled = numbers.clone();
+    pool.par_for_each(doubled.as_mut_slice(), |n| *n *= 2);
+    assert_eq!(doubled, Vec::from_iter((0..=1000).map(|n| n * 2)));
+}
+
+#[test]
+fn par_helpers_work_within_jobs_of_a_single_worker_pool() {
+    use std::sync::mpsc::channel;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let pool = Arc::new(ThreadPool::new(1));
+    let (sender, receiver) = channel();
+
+    let inner = Arc::clone(&pool);
+    pool.execute(move || {
+        let squares = inner.par_map(0..100, |n| n * n);
+        let sum = inner.par_reduce(0..100, || 0, |n| n, |a, b| a + b);
+        sender.send((squares, sum)).unwrap();
+    });
+
+    let (squares, sum) = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(squares, Vec::from_iter((0..100).map(|n| n * n)));
+    assert_eq!(sum, 4950);
+}
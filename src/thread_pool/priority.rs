@@ -0,0 +1,112 @@
+use std::sync::{Arc, Weak};
+
+use super::Shared;
+
+/// Workers pick up queued jobs with a higher priority first
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    Critical,
+}
+
+impl Priority {
+    pub const ALL: [Priority; 4] = [
+        Priority::Low,
+        Priority::Normal,
+        Priority::High,
+        Priority::Critical,
+    ];
+}
+
+/// Refers to a job queued with
+/// [`ThreadPool::execute_with_priority`](super::ThreadPool::execute_with_priority)
+pub struct JobHandle {
+    // None if the job was never queued, or dropped to make room for another
+    id: Option<u64>,
+    shared: Weak<Shared>,
+}
+
+impl JobHandle {
+    pub(super) fn new(shared: &Arc<Shared>, id: Option<u64>) -> Self {
+        Self {
+            id,
+            shared: Arc::downgrade(shared),
+        }
+    }
+
+    /// Moves the job to another priority. It keeps its place among the jobs
+    /// of that priority according to when it was queued, and doesn't lose
+    /// the time it already waited for aging. Returns false if the job isn't
+    /// queued anymore.
+    pub fn reprioritize(&self, priority: Priority) -> bool {
+        match (self.id, self.shared.upgrade()) {
+            (Some(id), Some(shared)) => shared.queue.reprioritize(id, priority),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+fn run_order(
+    builder: super::ThreadPoolBuilder,
+    submit: impl FnOnce(&super::ThreadPool, &dyn Fn(&'static str) -> super::Job),
+) -> Vec<&'static str> {
+    use std::sync::Mutex;
+
+    let (pool, unblock) = super::queue::blocked_pool(builder);
+    let order = Arc::new(Mutex::new(Vec::new()));
+
+    submit(&pool, &|name| {
+        let order = Arc::clone(&order);
+        Box::new(move || order.lock().unwrap().push(name))
+    });
+
+    unblock.send(()).unwrap();
+    pool.shutdown();
+
+    Arc::try_unwrap(order).unwrap().into_inner().unwrap()
+}
+
+#[test]
+fn higher_priorities_run_first() {
+    let order = run_order(super::ThreadPool::builder(), |pool, job| {
+        pool.execute_with_priority(Priority::Low, job("low"));
+        pool.execute_with_priority(Priority::Normal, job("normal"));
+        pool.execute_with_priority(Priority::Critical, job("critical"));
+        pool.execute_with_priority(Priority::High, job("high"));
+        pool.execute_with_priority(Priority::Normal, job("normal 2"));
+    });
+
+    assert_eq!(order, ["critical", "high", "normal", "normal 2", "low"]);
+}
+
+#[test]
+fn queued_jobs_can_be_reprioritized() {
+    let order = run_order(super::ThreadPool::builder(), |pool, job| {
+        let low = pool.execute_with_priority(Priority::Low, job("low"));
+        pool.execute_with_priority(Priority::High, job("high"));
+        pool.execute_with_priority(Priority::Normal, job("normal"));
+
+        assert!(low.reprioritize(Priority::Critical));
+    });
+
+    assert_eq!(order, ["low", "high", "normal"]);
+}
+
+#[test]
+fn waiting_jobs_age_into_higher_priorities() {
+    use std::thread;
+    use std::time::Duration;
+
+    let builder = super::ThreadPool::builder().priority_aging(Duration::from_millis(10));
+    let order = run_order(builder, |pool, job| {
+        pool.execute_with_priority(Priority::Low, job("low"));
+        thread::sleep(Duration::from_millis(100));
+        pool.execute_with_priority(Priority::Critical, job("critical"));
+    });
+
+    assert_eq!(order, ["low", "critical"]);
+}
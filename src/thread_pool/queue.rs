@@ -0,0 +1,348 @@
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use super::backend::{QueueBackend, Sent};
+use super::{Job, Message, Priority};
+use crate::mutex::{Condvar, Mutex};
+
+/// What [`ThreadPool::try_execute`](super::ThreadPool::try_execute) does
+/// when the queue is at capacity
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RejectionPolicy {
+    /// Wait until a worker picks up a queued job
+    Block,
+    /// Hand the job back as `Err(Rejected(job))`
+    Reject,
+    /// Run the job right away on the submitting thread
+    CallerRuns,
+    /// Drop the job that has been queued the longest to make room
+    DropOldest,
+}
+
+/// A job that didn't fit into the queue, see [`RejectionPolicy::Reject`]
+pub struct Rejected<F>(pub F);
+
+impl<F> fmt::Debug for Rejected<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Rejected(..)")
+    }
+}
+
+impl<F> fmt::Display for Rejected<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("job rejected, the queue is full")
+    }
+}
+
+impl<F> Error for Rejected<F> {}
+
+// Same as the Channel from 14-channels.rs, but receiving can time out, so
+// idle workers can retire, it can be closed to stop the workers, it may only
+// hold so many jobs sent with send_bounded, and jobs are received by priority
+pub(super) struct JobQueue {
+    state: Mutex<State>,
+    item_ready: Condvar,
+    space_available: Condvar,
+    capacity: Option<usize>,
+    policy: RejectionPolicy,
+    // a queued job is treated as one priority higher for every `aging` it waits
+    aging: Duration,
+}
+
+struct State {
+    // One FIFO queue per priority, each sorted by entry id
+    levels: [VecDeque<Entry>; Priority::ALL.len()],
+    // Number of entries that count towards the capacity
+    num_bounded: usize,
+    // once closed, workers get Message::Terminate after the last job
+    closed: bool,
+    next_id: u64,
+}
+
+struct Entry {
+    id: u64,
+    job: Job,
+    bounded: bool,
+    queued_at: Instant,
+}
+
+impl State {
+    fn push(&mut self, job: Job, priority: Priority, bounded: bool) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        if bounded {
+            self.num_bounded += 1;
+        }
+
+        self.levels[priority as usize].push_back(Entry {
+            id,
+            job,
+            bounded,
+            queued_at: Instant::now(),
+        });
+        id
+    }
+
+    // Takes the job with the highest priority after aging. Only the oldest
+    // job of every priority needs to be looked at, as it aged the most.
+    fn pop(&mut self, aging: Duration) -> Option<Entry> {
+        let now = Instant::now();
+        let (level, _) = self
+            .levels
+            .iter()
+            .enumerate()
+            .filter_map(|(level, jobs)| Some((level, jobs.front()?)))
+            .max_by_key(|(level, entry)| {
+                let waited = now.saturating_duration_since(entry.queued_at);
+                let aged = waited.as_nanos() / aging.as_nanos();
+                (*level as u128 + aged, *level, Reverse(entry.id))
+            })?;
+
+        let entry = self.levels[level].pop_front()?;
+        if entry.bounded {
+            self.num_bounded -= 1;
+        }
+        Some(entry)
+    }
+
+    // Removes the bounded job that was queued first, regardless of priority
+    fn remove_oldest_bounded(&mut self) -> Option<Job> {
+        let (level, index) = self
+            .levels
+            .iter()
+            .enumerate()
+            .filter_map(|(level, jobs)| {
+                let index = jobs.iter().position(|entry| entry.bounded)?;
+                Some((level, index))
+            })
+            .min_by_key(|&(level, index)| self.levels[level][index].id)?;
+
+        self.num_bounded -= 1;
+        self.levels[level].remove(index).map(|entry| entry.job)
+    }
+}
+
+impl JobQueue {
+    pub(super) fn new(capacity: Option<usize>, policy: RejectionPolicy, aging: Duration) -> Self {
+        Self {
+            state: Mutex::new(State {
+                levels: Default::default(),
+                num_bounded: 0,
+                closed: false,
+                next_id: 0,
+            }),
+            item_ready: Condvar::new(),
+            space_available: Condvar::new(),
+            capacity,
+            policy,
+            aging,
+        }
+    }
+}
+
+impl QueueBackend for JobQueue {
+    /// Queues a job regardless of the capacity. Used for jobs that must not
+    /// be dropped, like the ones of a Scope.
+    fn send(&self, job: Job) {
+        self.state.lock().push(job, Priority::Normal, false);
+        self.item_ready.notify_one();
+    }
+
+    /// Queues a job if there's room for it, otherwise applies the rejection
+    /// policy
+    fn send_bounded(&self, priority: Priority, make_job: &mut dyn FnMut() -> Job) -> Sent {
+        let mut state = self.state.lock();
+        let mut replaced = None;
+
+        if let Some(capacity) = self.capacity {
+            match self.policy {
+                RejectionPolicy::Block => {
+                    while state.num_bounded >= capacity {
+                        state = self.space_available.wait(state);
+                    }
+                }
+                RejectionPolicy::Reject | RejectionPolicy::CallerRuns => {
+                    if state.num_bounded >= capacity {
+                        return Sent::Full;
+                    }
+                }
+                RejectionPolicy::DropOldest => {
+                    if state.num_bounded >= capacity {
+                        replaced = state.remove_oldest_bounded();
+                    }
+                }
+            }
+        }
+
+        let id = state.push(make_job(), priority, true);
+        drop(state);
+
+        self.item_ready.notify_one();
+
+        match replaced {
+            Some(job) => Sent::ReplacedOldest(job, Some(id)),
+            None => Sent::Queued(Some(id)),
+        }
+    }
+
+    /// Moves a queued job to another priority, keeping the time it was queued
+    /// at. Returns false if the job isn't queued anymore.
+    fn reprioritize(&self, id: u64, priority: Priority) -> bool {
+        let mut state = self.state.lock();
+
+        for level in 0..state.levels.len() {
+            if let Some(index) = state.levels[level].iter().position(|entry| entry.id == id) {
+                let entry = state.levels[level].remove(index).unwrap();
+
+                let jobs = &mut state.levels[priority as usize];
+                let index = jobs.partition_point(|other| other.id < id);
+                jobs.insert(index, entry);
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Lets the workers terminate once all queued jobs are done
+    fn close(&self) {
+        self.state.lock().closed = true;
+        self.item_ready.notify_all();
+    }
+
+    /// Lets the workers terminate right away, returning the queued jobs in
+    /// the order they would have run in
+    fn close_and_drain(&self) -> Vec<Job> {
+        let mut state = self.state.lock();
+        state.closed = true;
+
+        let mut jobs = Vec::new();
+        while let Some(entry) = state.pop(self.aging) {
+            jobs.push(entry.job);
+        }
+        drop(state);
+
+        self.item_ready.notify_all();
+        self.space_available.notify_all();
+        jobs
+    }
+
+    /// Waits for the next message, or returns None if there was none within
+    /// `timeout`. Waits forever if `timeout` is None.
+    fn receive_timeout(&self, timeout: Option<Duration>) -> Option<Message> {
+        // too far away to be reached, e.g. for a keep_alive of Duration::MAX
+        let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        let mut b = self.state.lock();
+
+        loop {
+            if let Some(entry) = b.pop(self.aging) {
+                if entry.bounded {
+                    self.space_available.notify_one();
+                }
+                return Some(Message::NewJob(entry.job, entry.queued_at));
+            }
+            if b.closed {
+                return Some(Message::Terminate);
+            }
+
+            b = match deadline {
+                None => self.item_ready.wait(b),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return None;
+                    }
+
+                    self.item_ready.wait_timeout(b, deadline - now).0
+                }
+            };
+        }
+    }
+}
+
+// A pool with a single worker that's busy until the returned sender is used,
+// so that all jobs submitted in the meantime stay queued
+#[cfg(test)]
+pub(super) fn blocked_pool(
+    builder: super::ThreadPoolBuilder,
+) -> (super::ThreadPool, std::sync::mpsc::Sender<()>) {
+    use std::sync::mpsc::channel;
+
+    let pool = builder.num_threads(1).build().unwrap();
+
+    // keep the only worker busy until the test is done submitting jobs
+    let (started, wait_started) = channel();
+    let (unblock, wait_unblocked) = channel::<()>();
+    pool.execute(move || {
+        started.send(()).unwrap();
+        wait_unblocked.recv().unwrap();
+    });
+    wait_started.recv().unwrap();
+
+    (pool, unblock)
+}
+
+#[cfg(test)]
+fn bounded(policy: RejectionPolicy) -> super::ThreadPoolBuilder {
+    super::ThreadPool::builder()
+        .queue_capacity(2)
+        .rejection_policy(policy)
+}
+
+#[test]
+fn full_queue_rejects_jobs() {
+    let (pool, unblock) = blocked_pool(bounded(RejectionPolicy::Reject));
+
+    assert!(pool.try_execute(|| {}).is_ok());
+    assert!(pool.try_execute(|| {}).is_ok());
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let Err(Rejected(job)) = pool.try_execute(move || sender.send(42).unwrap()) else {
+        panic!("job should have been rejected");
+    };
+    job();
+    assert_eq!(receiver.try_recv(), Ok(42));
+
+    unblock.send(()).unwrap();
+}
+
+#[test]
+fn full_queue_runs_jobs_on_the_caller() {
+    let (pool, unblock) = blocked_pool(bounded(RejectionPolicy::CallerRuns));
+    let caller = std::thread::current().id();
+
+    pool.execute(|| {});
+    pool.execute(|| {});
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    pool.execute(move || sender.send(std::thread::current().id()).unwrap());
+    assert_eq!(receiver.try_recv(), Ok(caller));
+
+    unblock.send(()).unwrap();
+}
+
+#[test]
+fn full_queue_drops_oldest_jobs() {
+    use std::sync::{Arc, Mutex};
+
+    let (pool, unblock) = blocked_pool(bounded(RejectionPolicy::DropOldest));
+    let done = Arc::new(Mutex::new(Vec::new()));
+
+    let handles = Vec::from_iter((0..5).map(|i| {
+        let done = Arc::clone(&done);
+        pool.execute_with_priority(Priority::Low, move || done.lock().unwrap().push(i))
+    }));
+
+    // jobs that replaced older ones can still be reprioritized
+    assert!(!handles[0].reprioritize(Priority::High));
+    assert!(handles[4].reprioritize(Priority::High));
+
+    unblock.send(()).unwrap();
+    pool.shutdown();
+
+    assert_eq!(*done.lock().unwrap(), [4, 3]);
+}
@@ -0,0 +1,185 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::mem;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, Thread};
+
+use super::{Job, ThreadPool};
+
+/// A scope to spawn jobs on a [`ThreadPool`] that may borrow from the stack,
+/// the pool-backed counterpart of `std::thread::Scope`.
+pub struct Scope<'scope, 'env: 'scope> {
+    pool: &'scope ThreadPool,
+    data: Arc<ScopeData>,
+    // both lifetimes need to be invariant, just like in std::thread::Scope
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+struct ScopeData {
+    // Number of spawned jobs that have not finished yet
+    num_running: AtomicUsize,
+    // Spawned jobs nobody started yet. The pool only gets a job that runs
+    // the next one of these, so that the thread waiting for the scope can
+    // run them too.
+    pending: Mutex<VecDeque<Job>>,
+    // Payload of the first job that panicked, re-thrown when the scope ends
+    panic: Mutex<Option<Box<dyn Any + Send>>>,
+    // The thread waiting in ThreadPool::scope
+    main_thread: Thread,
+}
+
+impl ThreadPool {
+    /// Runs `f` with a [`Scope`] that jobs borrowing local data can be
+    /// spawned on. All spawned jobs have finished when this returns, and if
+    /// any of them panicked, the panic is propagated to the caller.
+    ///
+    /// While waiting, the calling thread runs spawned jobs no worker started
+    /// yet, so a scope doesn't deadlock when it's used from within a job on a
+    /// pool whose workers are all busy.
+    pub fn scope<'env, F, T>(&self, f: F) -> T
+    where
+        F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> T,
+    {
+        let scope = Scope {
+            pool: self,
+            data: Arc::new(ScopeData {
+                num_running: AtomicUsize::new(0),
+                pending: Mutex::new(VecDeque::new()),
+                panic: Mutex::new(None),
+                main_thread: thread::current(),
+            }),
+            scope: PhantomData,
+            env: PhantomData,
+        };
+
+        // even if f panics, jobs it already spawned might still borrow from
+        // the stack, so we have to wait for them before unwinding
+        let result = catch_unwind(AssertUnwindSafe(|| f(&scope)));
+
+        // help with the jobs instead of only waiting for the workers, which
+        // might all be busy, e.g. with the job that called this
+        loop {
+            let job = scope.data.pending.lock().unwrap().pop_front();
+            if let Some(job) = job {
+                job();
+                continue;
+            }
+
+            // thread::park() might return spuriously, so check again every time
+            if scope.data.num_running.load(Acquire) == 0 {
+                break;
+            }
+            thread::park();
+        }
+
+        let panic = scope.data.panic.lock().unwrap().take();
+
+        match (result, panic) {
+            (Err(payload), _) | (Ok(_), Some(payload)) => resume_unwind(payload),
+            (Ok(result), None) => result,
+        }
+    }
+}
+
+impl<'scope> Scope<'scope, '_> {
+    /// Queues `f` on the pool. Unlike [`ThreadPool::execute`], `f` only needs
+    /// to live as long as the scope.
+    pub fn spawn<F>(&'scope self, f: F)
+    where
+        F: FnOnce() + Send + 'scope,
+    {
+        if self.data.num_running.fetch_add(1, Relaxed) > usize::MAX / 2 {
+            std::process::abort();
+        }
+
+        let data = Arc::clone(&self.data);
+        let job: Box<dyn FnOnce() + Send + 'scope> = Box::new(move || {
+            if let Err(payload) = catch_unwind(AssertUnwindSafe(f)) {
+                data.panic.lock().unwrap().get_or_insert(payload);
+            }
+
+            // Release matches the Acquire load in ThreadPool::scope, to make
+            // sure everything this job did is visible once the scope ends
+            if data.num_running.fetch_sub(1, Release) == 1 {
+                data.main_thread.unpark();
+            }
+        });
+
+        // Safety: ThreadPool::scope doesn't return before num_running dropped
+        // back to zero, so the job can't outlive anything it borrows
+        let job: Job = unsafe { mem::transmute(job) };
+        self.data.pending.lock().unwrap().push_back(job);
+
+        // whoever comes first runs it, a worker or the waiting thread. The
+        // pool's job might only run once the scope ended, and then finds
+        // nothing left to do.
+        let data = Arc::clone(&self.data);
+        self.pool.send_job(Box::new(move || {
+            let job = data.pending.lock().unwrap().pop_front();
+            if let Some(job) = job {
+                job();
+            }
+        }));
+        self.data.main_thread.unpark();
+    }
+}
+
+#[test]
+fn scoped_jobs_borrow_from_the_stack() {
+    let pool = ThreadPool::new(4);
+    let numbers = Vec::from_iter(0..100);
+    let sum = AtomicUsize::new(0);
+
+    pool.scope(|s| {
+        for n in &numbers {
+            let sum = &sum;
+            s.spawn(move || {
+                sum.fetch_add(*n, Relaxed);
+            });
+        }
+    });
+
+    assert_eq!(sum.into_inner(), 4950);
+}
+
+#[test]
+#[should_panic(expected = "job failed")]
+fn scoped_job_panic_is_propagated() {
+    let pool = ThreadPool::new(2);
+
+    pool.scope(|s| {
+        s.spawn(|| panic!("job failed"));
+        s.spawn(|| {});
+    });
+}
+
+#[test]
+fn scopes_within_jobs_of_a_busy_pool_finish() {
+    use std::sync::mpsc::channel;
+
+    // the only worker waits for the scope, so it has to run the jobs itself
+    let pool = Arc::new(ThreadPool::new(1));
+    let (sender, receiver) = channel();
+
+    let inner = Arc::clone(&pool);
+    pool.execute(move || {
+        let sum = AtomicUsize::new(0);
+        inner.scope(|s| {
+            for n in 0..10 {
+                let sum = &sum;
+                s.spawn(move || {
+                    sum.fetch_add(n, Relaxed);
+                });
+            }
+        });
+        sender.send(sum.into_inner()).unwrap();
+    });
+
+    let sum = receiver.recv_timeout(std::time::Duration::from_secs(5));
+    assert_eq!(sum, Ok(45));
+}
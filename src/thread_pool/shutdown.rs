@@ -0,0 +1,151 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::Ordering::Relaxed;
+use std::time::Duration;
+
+use super::{Job, ThreadPool};
+
+use crate::deadline;
+
+/// Returned by [`ThreadPool::shutdown_timeout`] when not all workers stopped
+/// in time. Those workers are left running in the background.
+#[derive(Debug)]
+pub struct ShutdownTimedOut {
+    workers: Vec<usize>,
+}
+
+impl ShutdownTimedOut {
+    /// Ids of the workers that were still running
+    pub fn workers(&self) -> &[usize] {
+        &self.workers
+    }
+}
+
+impl fmt::Display for ShutdownTimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "workers {:?} did not stop in time", self.workers)
+    }
+}
+
+impl Error for ShutdownTimedOut {}
+
+impl ThreadPool {
+    /// Runs all queued jobs, then stops the workers and waits for them.
+    /// Dropping the pool does the same.
+    pub fn shutdown(self) {
+        drop(self);
+    }
+
+    /// Stops the workers as soon as their current job is done and waits for
+    /// them. Jobs that didn't start yet are returned instead of run.
+    pub fn shutdown_now(self) -> Vec<Job> {
+        self.stop_timer();
+
+        let jobs = self.shared.queue.close_and_drain();
+        self.shared.queued.fetch_sub(jobs.len(), Relaxed);
+        self.shared.jobs_done(jobs.len());
+
+        drop(self);
+        jobs
+    }
+
+    /// Like [`ThreadPool::shutdown`], but gives up waiting for the workers
+    /// after `timeout`, for example when a job is stuck.
+    pub fn shutdown_timeout(self, timeout: Duration) -> Result<(), ShutdownTimedOut> {
+        let deadline = deadline::after(timeout);
+        self.stop_timer();
+        self.shared.queue.close();
+
+        let mut workers = self.shared.workers.lock();
+        while !workers.running.is_empty() {
+            let Some(timeout) = deadline::remaining(deadline) else {
+                break;
+            };
+
+            workers = self.shared.worker_exited.wait_timeout(workers, timeout).0;
+        }
+
+        let mut stuck = Vec::from_iter(workers.running.iter().copied());
+        stuck.sort_unstable();
+
+        // detach the stuck workers, so dropping the pool only joins the others
+        for id in &stuck {
+            workers.threads.remove(id);
+        }
+        drop(workers);
+        drop(self);
+
+        if stuck.is_empty() {
+            Ok(())
+        } else {
+            Err(ShutdownTimedOut { workers: stuck })
+        }
+    }
+}
+
+#[test]
+fn shutdown_runs_queued_jobs() {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+
+    let pool = ThreadPool::new(2);
+    let done = Arc::new(AtomicUsize::new(0));
+
+    for _ in 0..100 {
+        let done = Arc::clone(&done);
+        pool.execute(move || {
+            done.fetch_add(1, Relaxed);
+        });
+    }
+
+    pool.shutdown();
+    assert_eq!(done.load(Relaxed), 100);
+}
+
+#[test]
+fn shutdown_now_returns_queued_jobs() {
+    use std::sync::mpsc::channel;
+    use std::thread;
+
+    let pool = ThreadPool::new(1);
+    let (sender, receiver) = channel();
+
+    pool.execute(move || {
+        sender.send(()).unwrap();
+        thread::sleep(Duration::from_millis(50));
+    });
+    receiver.recv().unwrap();
+
+    // the only worker is busy, so these stay queued
+    for _ in 0..3 {
+        pool.execute(|| unreachable!());
+    }
+
+    assert_eq!(pool.shutdown_now().len(), 3);
+}
+
+#[test]
+fn shutdown_timeout_reports_stuck_workers() {
+    use std::sync::atomic::AtomicBool;
+    use std::sync::mpsc::channel;
+
+    static STOP: AtomicBool = AtomicBool::new(false);
+
+    let pool = ThreadPool::new(2);
+    let (sender, receiver) = channel();
+
+    pool.execute(move || {
+        sender.send(()).unwrap();
+        while !STOP.load(Relaxed) {
+            std::hint::spin_loop();
+        }
+    });
+    receiver.recv().unwrap();
+
+    let error = pool
+        .shutdown_timeout(Duration::from_millis(50))
+        .unwrap_err();
+    assert_eq!(error.workers().len(), 1);
+
+    STOP.store(true, Relaxed);
+}
@@ -0,0 +1,184 @@
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::atomic::{AtomicU64, AtomicUsize};
+use std::time::Duration;
+
+use super::ThreadPool;
+
+/// Reported to the hook set with
+/// [`ThreadPoolBuilder::on_event`](super::ThreadPoolBuilder::on_event)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PoolEvent {
+    WorkerStarted {
+        worker: usize,
+    },
+    JobStarted {
+        worker: usize,
+        queue_wait: Duration,
+    },
+    JobFinished {
+        worker: usize,
+        execution_time: Duration,
+        panicked: bool,
+    },
+    /// The worker was idle for longer than the keep-alive timeout
+    WorkerRetired {
+        worker: usize,
+    },
+    /// The pool was shut down
+    WorkerTerminated {
+        worker: usize,
+    },
+}
+
+/// A snapshot of the pool's counters, see [`ThreadPool::stats`]. As the
+/// counters are updated independently, they might not add up exactly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolStats {
+    pub queued_jobs: usize,
+    pub num_workers: usize,
+    /// Number of workers running a job right now
+    pub active_workers: usize,
+    /// Number of jobs that ran to completion
+    pub completed_jobs: u64,
+    pub panicked_jobs: u64,
+    pub total_execution_time: Duration,
+    pub max_execution_time: Duration,
+    /// Time from queueing a job until a worker picked it up, summed up
+    pub total_queue_wait: Duration,
+    pub max_queue_wait: Duration,
+}
+
+impl PoolStats {
+    pub fn average_execution_time(&self) -> Option<Duration> {
+        let finished = self.completed_jobs + self.panicked_jobs;
+        (finished > 0).then(|| average(self.total_execution_time, finished))
+    }
+
+    pub fn average_queue_wait(&self) -> Option<Duration> {
+        let started = self.completed_jobs + self.panicked_jobs + self.active_workers as u64;
+        (started > 0).then(|| average(self.total_queue_wait, started))
+    }
+}
+
+// dividing the Duration itself would need the count as u32, which truncates
+fn average(total: Duration, count: u64) -> Duration {
+    Duration::from_nanos((total.as_nanos() / count as u128) as u64)
+}
+
+// Same fetch_add/fetch_max pattern as in 11-progress-reporting-multiple-threads.rs
+pub(super) struct Stats {
+    active: AtomicUsize,
+    completed: AtomicU64,
+    panicked: AtomicU64,
+    // in microseconds
+    total_execution_time: AtomicU64,
+    max_execution_time: AtomicU64,
+    total_queue_wait: AtomicU64,
+    max_queue_wait: AtomicU64,
+}
+
+impl Stats {
+    pub(super) fn new() -> Self {
+        Self {
+            active: AtomicUsize::new(0),
+            completed: AtomicU64::new(0),
+            panicked: AtomicU64::new(0),
+            total_execution_time: AtomicU64::new(0),
+            max_execution_time: AtomicU64::new(0),
+            total_queue_wait: AtomicU64::new(0),
+            max_queue_wait: AtomicU64::new(0),
+        }
+    }
+
+    pub(super) fn job_started(&self, queue_wait: Duration) {
+        let queue_wait = queue_wait.as_micros() as u64;
+
+        self.active.fetch_add(1, Relaxed);
+        self.total_queue_wait.fetch_add(queue_wait, Relaxed);
+        self.max_queue_wait.fetch_max(queue_wait, Relaxed);
+    }
+
+    pub(super) fn job_finished(&self, execution_time: Duration, panicked: bool) {
+        let execution_time = execution_time.as_micros() as u64;
+
+        if panicked {
+            self.panicked.fetch_add(1, Relaxed);
+        } else {
+            self.completed.fetch_add(1, Relaxed);
+        }
+        self.total_execution_time.fetch_add(execution_time, Relaxed);
+        self.max_execution_time.fetch_max(execution_time, Relaxed);
+        self.active.fetch_sub(1, Relaxed);
+    }
+}
+
+impl ThreadPool {
+    pub fn stats(&self) -> PoolStats {
+        let stats = &self.shared.stats;
+
+        PoolStats {
+            queued_jobs: self.shared.queued.load(Relaxed),
+            num_workers: self.num_threads(),
+            active_workers: stats.active.load(Relaxed),
+            completed_jobs: stats.completed.load(Relaxed),
+            panicked_jobs: stats.panicked.load(Relaxed),
+            total_execution_time: Duration::from_micros(stats.total_execution_time.load(Relaxed)),
+            max_execution_time: Duration::from_micros(stats.max_execution_time.load(Relaxed)),
+            total_queue_wait: Duration::from_micros(stats.total_queue_wait.load(Relaxed)),
+            max_queue_wait: Duration::from_micros(stats.max_queue_wait.load(Relaxed)),
+        }
+    }
+}
+
+#[test]
+fn stats_and_events_track_jobs() {
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let pool = {
+        let events = Arc::clone(&events);
+        ThreadPool::builder()
+            .num_threads(2)
+            .on_event(move |event| events.lock().unwrap().push(event))
+            .build()
+            .unwrap()
+    };
+
+    for i in 0..10 {
+        pool.execute(move || {
+            thread::sleep(Duration::from_millis(5));
+            if i % 5 == 0 {
+                panic!("job {i} failed");
+            }
+        });
+    }
+
+    // workers survive panicking jobs
+    while pool.stats().completed_jobs + pool.stats().panicked_jobs < 10 {
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    let stats = pool.stats();
+    assert_eq!(stats.num_workers, 2);
+    assert_eq!(stats.queued_jobs, 0);
+    assert_eq!(stats.completed_jobs, 8);
+    assert_eq!(stats.panicked_jobs, 2);
+    assert!(stats.max_execution_time >= Duration::from_millis(5));
+    assert!(stats.average_execution_time().unwrap() <= stats.max_execution_time);
+
+    pool.shutdown();
+
+    let events = events.lock().unwrap();
+    let count = |f: fn(&PoolEvent) -> bool| events.iter().filter(|e| f(e)).count();
+    assert_eq!(count(|e| matches!(e, PoolEvent::WorkerStarted { .. })), 2);
+    assert_eq!(count(|e| matches!(e, PoolEvent::JobStarted { .. })), 10);
+    assert_eq!(
+        count(|e| matches!(e, PoolEvent::JobFinished { panicked: true, .. })),
+        2
+    );
+    assert_eq!(
+        count(|e| matches!(e, PoolEvent::WorkerTerminated { .. })),
+        2
+    );
+}
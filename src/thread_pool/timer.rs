@@ -0,0 +1,489 @@
+use std::mem;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::sync::{Arc, Condvar, Mutex, PoisonError, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::{Job, Shared, ThreadPool};
+
+// Every level of the wheel has 64 slots, each covering 64 times the time of
+// a slot in the level below. With 1ms ticks, 6 levels cover about two years.
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+const LEVELS: usize = 6;
+const TICK: Duration = Duration::from_millis(1);
+// Longer delays and periods are cut down to this, a timer wouldn't fire
+// anyway, and it keeps deadlines far from overflowing
+const MAX_DELAY: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+
+/// Refers to a job scheduled with [`ThreadPool::schedule_after`] or
+/// [`ThreadPool::schedule_at_fixed_rate`]
+#[derive(Clone)]
+pub struct TimerHandle {
+    state: Arc<TimerState>,
+}
+
+impl TimerHandle {
+    /// Stops the job from being run again. A run that already started isn't
+    /// interrupted.
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Relaxed);
+
+        // dropped right away, instead of once it would have been due
+        if let Task::Once(job) = &self.state.task {
+            let job = job.lock().unwrap().take();
+            drop(job);
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Relaxed)
+    }
+}
+
+struct TimerState {
+    // cancelled timers stay in the wheel until they're due, and are skipped
+    cancelled: AtomicBool,
+    task: Task,
+}
+
+enum Task {
+    Once(Mutex<Option<Job>>),
+    FixedRate {
+        f: Arc<dyn Fn() + Send + Sync>,
+        period: u64,
+        // a run is skipped if the previous one is still running
+        running: Arc<AtomicBool>,
+    },
+}
+
+struct Entry {
+    // in ticks since the wheel was created
+    deadline: u64,
+    state: Arc<TimerState>,
+}
+
+// A hierarchical timer wheel: timers due within the current 64 ticks are in
+// level 0, one slot per tick. Timers further out are in higher levels, and
+// get moved down a level (cascaded) once the current tick reaches their slot.
+struct Wheel {
+    levels: [[Vec<Entry>; SLOTS]; LEVELS],
+    start: Instant,
+    // all timers up to and including this tick have been fired
+    tick: u64,
+    len: usize,
+    stopped: bool,
+}
+
+impl Wheel {
+    fn new() -> Self {
+        Self {
+            levels: std::array::from_fn(|_| std::array::from_fn(|_| Vec::new())),
+            start: Instant::now(),
+            tick: 0,
+            len: 0,
+            stopped: false,
+        }
+    }
+
+    // Ticks passed since the wheel was created, rounded down
+    fn elapsed(&self) -> u64 {
+        (self.start.elapsed().as_nanos() / TICK.as_nanos()) as u64
+    }
+
+    // The first tick at or after `delay` from now
+    fn deadline_after(&self, delay: Duration) -> u64 {
+        let deadline = (self.start.elapsed() + delay.min(MAX_DELAY))
+            .as_nanos()
+            .div_ceil(TICK.as_nanos());
+        deadline as u64
+    }
+
+    fn insert(&mut self, mut entry: Entry) {
+        // the current tick has been processed already
+        entry.deadline = entry.deadline.max(self.tick + 1);
+
+        // the highest group of bits where deadline and current tick differ
+        // decides the level, so the slot is reached exactly when all higher
+        // bits of the tick match the deadline
+        let diff = entry.deadline ^ self.tick;
+        let level = if diff < SLOTS as u64 {
+            0
+        } else {
+            ((63 - diff.leading_zeros()) / SLOT_BITS).min(LEVELS as u32 - 1)
+        };
+        let slot = (entry.deadline >> (level * SLOT_BITS)) as usize % SLOTS;
+
+        self.levels[level as usize][slot].push(entry);
+        self.len += 1;
+    }
+
+    // Processes all ticks up to and including `target`, returning the timers
+    // that are due
+    fn advance_to(&mut self, target: u64) -> Vec<Entry> {
+        let mut due = Vec::new();
+
+        while self.tick < target {
+            self.tick += 1;
+
+            // cascade from the top, as entries may move down more than one level
+            for level in (1..LEVELS).rev() {
+                let shift = level as u32 * SLOT_BITS;
+                if self.tick & ((1 << shift) - 1) != 0 {
+                    continue;
+                }
+
+                let slot = (self.tick >> shift) as usize % SLOTS;
+                for entry in mem::take(&mut self.levels[level][slot]) {
+                    self.len -= 1;
+                    if entry.deadline <= self.tick {
+                        due.push(entry);
+                    } else {
+                        self.insert(entry);
+                    }
+                }
+            }
+
+            let slot = self.tick as usize % SLOTS;
+            let entries = mem::take(&mut self.levels[0][slot]);
+            self.len -= entries.len();
+            due.extend(entries);
+        }
+
+        due
+    }
+
+    // Number of ticks until advancing the wheel may fire or cascade timers,
+    // or None if there are no timers at all
+    fn ticks_until_next(&self) -> Option<u64> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let next_cascade = (self.tick | (SLOTS as u64 - 1)) + 1;
+        let next_due = (self.tick + 1..next_cascade)
+            .find(|&tick| !self.levels[0][tick as usize % SLOTS].is_empty())
+            .unwrap_or(next_cascade);
+
+        Some(next_due - self.tick)
+    }
+}
+
+pub(super) struct Timer {
+    wheel: Arc<TimerWheel>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+struct TimerWheel {
+    wheel: Mutex<Wheel>,
+    changed: Condvar,
+}
+
+impl Timer {
+    fn start(shared: &Arc<Shared>) -> Self {
+        let wheel = Arc::new(TimerWheel {
+            wheel: Mutex::new(Wheel::new()),
+            changed: Condvar::new(),
+        });
+
+        let mut builder = thread::Builder::new();
+        if let Some(prefix) = &shared.config.thread_name_prefix {
+            builder = builder.name(format!("{prefix}timer"));
+        }
+
+        // the timer thread only gets a Weak, as Shared owns the Timer
+        let thread = {
+            let wheel = Arc::clone(&wheel);
+            let shared = Arc::downgrade(shared);
+            builder
+                .spawn(move || wheel.run(&shared))
+                .expect("failed to spawn timer thread")
+        };
+
+        Timer {
+            wheel,
+            thread: Mutex::new(Some(thread)),
+        }
+    }
+
+    fn schedule(&self, delay: Duration, task: Task) -> TimerHandle {
+        self.wheel.schedule(delay, task)
+    }
+
+    // Stops the timer thread, dropping all jobs that weren't due yet. Called
+    // when dropping the pool, so it doesn't panic if the timer thread did.
+    fn stop(&self) {
+        let mut wheel = self
+            .wheel
+            .wheel
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        wheel.stopped = true;
+        drop(wheel);
+        self.wheel.changed.notify_one();
+
+        let thread = self
+            .thread
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        if let Some(thread) = thread {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl TimerWheel {
+    fn schedule(&self, delay: Duration, task: Task) -> TimerHandle {
+        let state = Arc::new(TimerState {
+            cancelled: AtomicBool::new(false),
+            task,
+        });
+
+        let mut wheel = self.wheel.lock().unwrap();
+        let deadline = wheel.deadline_after(delay);
+        wheel.insert(Entry {
+            deadline,
+            state: Arc::clone(&state),
+        });
+        drop(wheel);
+
+        // the timer thread might be waiting for a later deadline
+        self.changed.notify_one();
+
+        TimerHandle { state }
+    }
+
+    fn run(&self, shared: &Weak<Shared>) {
+        let mut wheel = self.wheel.lock().unwrap();
+
+        while !wheel.stopped {
+            let now = wheel.elapsed();
+            let due = wheel.advance_to(now);
+
+            if !due.is_empty() {
+                let mut jobs = Vec::with_capacity(due.len());
+                for entry in due {
+                    if let Some(job) = fire(&mut wheel, entry) {
+                        jobs.push(job);
+                    }
+                }
+
+                // don't hold the lock while queueing jobs
+                drop(wheel);
+                let Some(shared) = shared.upgrade() else {
+                    return;
+                };
+                for job in jobs {
+                    shared.send_job(job);
+                }
+                drop(shared);
+
+                wheel = self.wheel.lock().unwrap();
+                continue;
+            }
+
+            wheel = match wheel.ticks_until_next() {
+                None => self.changed.wait(wheel).unwrap(),
+                Some(ticks) => {
+                    let tick_nanos = TICK.as_nanos() as u64;
+                    let until =
+                        wheel.start + Duration::from_nanos((wheel.tick + ticks) * tick_nanos);
+                    let timeout = until.saturating_duration_since(Instant::now());
+                    self.changed.wait_timeout(wheel, timeout).unwrap().0
+                }
+            };
+        }
+    }
+}
+
+// Returns the job to queue for a due timer, and puts it back into the wheel
+// if it's periodic
+fn fire(wheel: &mut Wheel, entry: Entry) -> Option<Job> {
+    if entry.state.cancelled.load(Relaxed) {
+        return None;
+    }
+
+    match &entry.state.task {
+        Task::Once(job) => job.lock().unwrap().take(),
+        Task::FixedRate { f, period, running } => {
+            let f = Arc::clone(f);
+            let running = Arc::clone(running);
+            let period = *period;
+
+            // the next run is relative to the schedule, not to when this one
+            // happens to start
+            wheel.insert(Entry {
+                deadline: entry.deadline + period,
+                state: entry.state,
+            });
+
+            if running.swap(true, Acquire) {
+                return None;
+            }
+
+            Some(Box::new(move || {
+                let _running = Running(running);
+                f();
+            }))
+        }
+    }
+}
+
+// Clears the running flag of a periodic job once a run is over, even if it
+// panicked, so the next runs aren't skipped forever
+struct Running(Arc<AtomicBool>);
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.0.store(false, Release);
+    }
+}
+
+impl ThreadPool {
+    /// Queues `f` on the pool once `delay` has passed. Like jobs spawned on a
+    /// [`Scope`](super::Scope), it doesn't count towards the queue capacity.
+    pub fn schedule_after<F>(&self, delay: Duration, f: F) -> TimerHandle
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.timer()
+            .schedule(delay, Task::Once(Mutex::new(Some(Box::new(f)))))
+    }
+
+    /// Queues `f` on the pool every `period`, starting one `period` from now,
+    /// until the returned handle is cancelled. If a run is still going when
+    /// the next one is due, the next one is skipped.
+    pub fn schedule_at_fixed_rate<F>(&self, period: Duration, f: F) -> TimerHandle
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        let ticks = (period.min(MAX_DELAY).as_nanos() / TICK.as_nanos()).max(1) as u64;
+
+        self.timer().schedule(
+            period,
+            Task::FixedRate {
+                f: Arc::new(f),
+                period: ticks,
+                running: Arc::new(AtomicBool::new(false)),
+            },
+        )
+    }
+
+    fn timer(&self) -> &Timer {
+        self.shared.timer.get_or_init(|| Timer::start(&self.shared))
+    }
+
+    pub(super) fn stop_timer(&self) {
+        if let Some(timer) = self.shared.timer.get() {
+            timer.stop();
+        }
+    }
+}
+
+#[test]
+fn wheel_fires_timers_at_their_deadline() {
+    let mut wheel = Wheel::new();
+
+    for deadline in [1, 63, 64, 65, 4095, 4096, 300_000] {
+        wheel.insert(Entry {
+            deadline,
+            state: Arc::new(TimerState {
+                cancelled: AtomicBool::new(false),
+                task: Task::Once(Mutex::new(None)),
+            }),
+        });
+    }
+
+    let mut fired = Vec::new();
+    while wheel.len > 0 {
+        let ticks = wheel.ticks_until_next().unwrap();
+        let target = wheel.tick + ticks;
+        for entry in wheel.advance_to(target) {
+            assert_eq!(entry.deadline, target);
+            fired.push(entry.deadline);
+        }
+    }
+
+    assert_eq!(fired, [1, 63, 64, 65, 4095, 4096, 300_000]);
+}
+
+#[test]
+fn scheduled_jobs_run_and_can_be_cancelled() {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::mpsc::channel;
+
+    let pool = ThreadPool::new(2);
+
+    let (sender, receiver) = channel();
+    let start = Instant::now();
+    pool.schedule_after(Duration::from_millis(50), move || {
+        sender.send(start.elapsed()).unwrap();
+    });
+    let cancelled = pool.schedule_after(Duration::from_millis(50), || unreachable!());
+    cancelled.cancel();
+
+    let runs = Arc::new(AtomicUsize::new(0));
+    let periodic = {
+        let runs = Arc::clone(&runs);
+        pool.schedule_at_fixed_rate(Duration::from_millis(10), move || {
+            runs.fetch_add(1, Relaxed);
+        })
+    };
+
+    assert!(receiver.recv().unwrap() >= Duration::from_millis(50));
+
+    periodic.cancel();
+    let after_cancel = runs.load(Relaxed);
+    assert!(after_cancel >= 1, "{after_cancel}");
+
+    // at most one run might have been queued already when cancelling
+    thread::sleep(Duration::from_millis(50));
+    assert!(runs.load(Relaxed) <= after_cancel + 1);
+}
+
+#[test]
+fn fixed_rate_jobs_keep_running_after_a_panic() {
+    use std::sync::atomic::AtomicUsize;
+
+    let pool = ThreadPool::new(1);
+
+    let runs = Arc::new(AtomicUsize::new(0));
+    let periodic = {
+        let runs = Arc::clone(&runs);
+        pool.schedule_at_fixed_rate(Duration::from_millis(5), move || {
+            if runs.fetch_add(1, Relaxed) == 0 {
+                panic!("only the first run panics");
+            }
+        })
+    };
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while runs.load(Relaxed) < 3 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(5));
+    }
+    periodic.cancel();
+
+    assert!(runs.load(Relaxed) >= 3);
+}
+
+#[test]
+fn far_away_and_cancelled_timers() {
+    let pool = ThreadPool::new(1);
+
+    // never due, but neither overflows
+    pool.schedule_after(Duration::MAX, || unreachable!());
+    pool.schedule_at_fixed_rate(Duration::MAX, || unreachable!());
+
+    // the job and everything it holds is dropped when cancelling
+    let held = Arc::new(());
+    let cancelled = {
+        let held = Arc::clone(&held);
+        pool.schedule_after(Duration::from_secs(60), move || drop(held))
+    };
+    cancelled.cancel();
+    assert_eq!(Arc::strong_count(&held), 1);
+
+    drop(pool);
+}
@@ -0,0 +1,125 @@
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::deadline;
+use crate::futex::{wait, wait_timeout, wake_all};
+
+/// Waits for work to be done, like Go's `sync.WaitGroup`: every clone stands
+/// for some outstanding work, and is dropped once it's done. Unlike a
+/// [`CountDownLatch`](crate::latch::CountDownLatch), the amount of work
+/// doesn't have to be known upfront, workers can hand out clones to more
+/// workers.
+pub struct WaitGroup {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    // the number of WaitGroups still around
+    count: AtomicU32,
+}
+
+impl WaitGroup {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                count: AtomicU32::new(1),
+            }),
+        }
+    }
+
+    /// Drops this one, then blocks until all clones are dropped too
+    pub fn wait(self) {
+        let inner = Arc::clone(&self.inner);
+        drop(self);
+
+        loop {
+            let count = inner.count.load(Acquire);
+            if count == 0 {
+                return;
+            }
+            wait(&inner.count, count);
+        }
+    }
+
+    /// Like [`wait`](Self::wait), but gives up after `timeout`. Returns
+    /// true if all clones were dropped.
+    pub fn wait_timeout(self, timeout: Duration) -> bool {
+        let deadline = deadline::after(timeout);
+        let inner = Arc::clone(&self.inner);
+        drop(self);
+
+        loop {
+            let count = inner.count.load(Acquire);
+            if count == 0 {
+                return true;
+            }
+
+            let Some(timeout) = deadline::remaining(deadline) else {
+                return false;
+            };
+            wait_timeout(&inner.count, count, timeout);
+        }
+    }
+}
+
+impl Clone for WaitGroup {
+    fn clone(&self) -> Self {
+        // the clone is made from one that's still around, so it can't be 0
+        self.inner.count.fetch_add(1, Relaxed);
+
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl Drop for WaitGroup {
+    fn drop(&mut self) {
+        // Release, so the waiting threads see all that was done before
+        if self.inner.count.fetch_sub(1, Release) == 1 {
+            wake_all(&self.inner.count);
+        }
+    }
+}
+
+impl Default for WaitGroup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn waits_for_clones_handed_out_by_workers() {
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+
+    let done = Arc::new(AtomicUsize::new(0));
+    let wait_group = WaitGroup::new();
+
+    for _ in 0..8 {
+        let (wait_group, done) = (wait_group.clone(), Arc::clone(&done));
+        thread::spawn(move || {
+            // every worker starts some more
+            for _ in 0..4 {
+                let (wait_group, done) = (wait_group.clone(), Arc::clone(&done));
+                thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(10));
+                    done.fetch_add(1, Relaxed);
+                    drop(wait_group);
+                });
+            }
+            done.fetch_add(1, Relaxed);
+        });
+    }
+
+    wait_group.wait();
+    assert_eq!(done.load(Relaxed), 8 + 8 * 4);
+
+    let wait_group = WaitGroup::new();
+    let pending = wait_group.clone();
+    assert!(!wait_group.clone().wait_timeout(Duration::from_millis(10)));
+    drop(pending);
+    assert!(wait_group.wait_timeout(Duration::from_secs(5)));
+}